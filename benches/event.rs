@@ -0,0 +1,38 @@
+//! Run with `cargo bench --features bench` on Redox.
+//!
+//! This is a plain `harness = false` binary rather than a criterion benchmark: criterion and its
+//! dependencies would have to be ported to Redox first, and the measurements in
+//! [`event::bench`] already do the timing, so they can also be run from applications.
+use std::time::Duration;
+
+use event::bench::{self, Measurement};
+
+const ITERATIONS: u32 = 10_000;
+const BATCH: usize = 64;
+const SPIN: Duration = Duration::from_micros(50);
+
+fn report(name: &str, measurement: Measurement) {
+    println!(
+        "{name:<14} mean {:>10?}  min {:>10?}  max {:>10?}  ({} iterations)",
+        measurement.mean(),
+        measurement.min,
+        measurement.max,
+        measurement.iterations
+    );
+}
+
+fn main() -> std::io::Result<()> {
+    report("subscribe", bench::measure_subscribe(ITERATIONS)?);
+    report("wakeup", bench::measure_roundtrip(ITERATIONS)?);
+    report(
+        "pingpong",
+        bench::measure_pingpong(ITERATIONS, Duration::ZERO)?,
+    );
+    report("pingpong-spin", bench::measure_pingpong(ITERATIONS, SPIN)?);
+    report("batch", bench::measure_batch(BATCH, ITERATIONS / 10)?);
+    report(
+        "batch-soa",
+        bench::measure_batch_columns(BATCH, ITERATIONS / 10)?,
+    );
+    Ok(())
+}
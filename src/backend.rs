@@ -0,0 +1,74 @@
+//! Choosing the queue implementation when the queue is created rather than at compile time.
+use alloc::boxed::Box;
+
+#[cfg(not(target_os = "redox"))]
+use libredox::errno::ENOSYS;
+#[cfg(not(target_os = "redox"))]
+use libredox::error::Error;
+use libredox::error::Result;
+
+use crate::{Event, EventFlags, EventQueueApi, MockQueue, UserData};
+
+/// An implementation of [`EventQueueApi`] that a [`DynQueue`] can delegate to
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// The kernel's event queue, through the version 1 ABI
+    RedoxV1,
+    /// A [`MockQueue`], whose events are posted by the program itself
+    Mock,
+}
+
+/// An event queue whose backend is picked at runtime, e.g. by a test harness
+///
+/// Every call goes through a vtable. Code that always runs on the same backend should use its
+/// queue type directly, or be generic over [`EventQueueApi`].
+pub struct DynQueue<U: UserData> {
+    backend: Backend,
+    inner: Box<dyn EventQueueApi<U>>,
+}
+
+impl<U: UserData + 'static> DynQueue<U> {
+    /// Create a new queue of the given backend
+    ///
+    /// Fails with `ENOSYS` if the backend is not available on the running system.
+    pub fn new(backend: Backend) -> Result<Self> {
+        let inner: Box<dyn EventQueueApi<U>> = match backend {
+            #[cfg(target_os = "redox")]
+            Backend::RedoxV1 => Box::new(crate::EventQueue::new()?),
+            #[cfg(not(target_os = "redox"))]
+            Backend::RedoxV1 => return Err(Error::new(ENOSYS)),
+            Backend::Mock => Box::new(MockQueue::new()),
+        };
+        Ok(Self { backend, inner })
+    }
+}
+impl<U: UserData> DynQueue<U> {
+    #[inline]
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+    /// The queue to post events to, if the backend is [`Backend::Mock`]
+    pub fn as_mock(&self) -> Option<&MockQueue<U>> {
+        if self.backend != Backend::Mock {
+            return None;
+        }
+        let inner: *const dyn EventQueueApi<U> = &*self.inner;
+        // SAFETY: Queues with the mock backend are only ever created around a MockQueue<U>.
+        Some(unsafe { &*inner.cast::<MockQueue<U>>() })
+    }
+}
+impl<U: UserData> EventQueueApi<U> for DynQueue<U> {
+    #[inline]
+    fn subscribe(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        self.inner.subscribe(fd, data, flags)
+    }
+    #[inline]
+    fn unsubscribe(&self, fd: usize) -> Result<()> {
+        self.inner.unsubscribe(fd)
+    }
+    #[inline]
+    fn wait(&self) -> Result<Event<U>> {
+        self.inner.wait()
+    }
+}
@@ -0,0 +1,173 @@
+//! Self-measurement of the event path, for regression tracking of kernel and crate changes.
+//!
+//! Events are generated by writing to pipes, so every measurement includes the cost of the
+//! write and of reading the data back.
+use std::io::{self, pipe, PipeReader, PipeWriter, Read, Write};
+use std::os::fd::AsRawFd;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use crate::wrappers::io_error;
+use crate::{EventColumns, EventFlags, EventQueue, RawEvent, RawEventQueue};
+
+/// Timing of a repeated operation
+#[derive(Clone, Copy, Debug)]
+pub struct Measurement {
+    pub iterations: u32,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl Measurement {
+    fn new() -> Self {
+        Self {
+            iterations: 0,
+            total: Duration::ZERO,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+        }
+    }
+    fn record(&mut self, elapsed: Duration) {
+        self.iterations += 1;
+        self.total += elapsed;
+        self.min = self.min.min(elapsed);
+        self.max = self.max.max(elapsed);
+    }
+    /// The average duration of one iteration
+    pub fn mean(&self) -> Duration {
+        self.total / self.iterations.max(1)
+    }
+}
+
+/// Measure the latency from making an fd readable until the event for it is read from a queue
+pub fn measure_roundtrip(iterations: u32) -> io::Result<Measurement> {
+    let queue = RawEventQueue::new().map_err(io_error)?;
+    let (mut reader, mut writer) = pipe()?;
+    queue
+        .subscribe(reader.as_raw_fd() as usize, 0, EventFlags::READ)
+        .map_err(io_error)?;
+
+    let mut measurement = Measurement::new();
+    for _ in 0..iterations {
+        let start = Instant::now();
+        writer.write_all(&[0])?;
+        queue.next_event().map_err(io_error)?;
+        measurement.record(start.elapsed());
+
+        reader.read_exact(&mut [0])?;
+    }
+    Ok(measurement)
+}
+
+/// Measure the round trip to a thread echoing bytes back, with the queue polling for `spin`
+/// before blocking
+///
+/// See [`EventQueue::set_spin_before_block`]. Comparing a zero `spin` with one longer than the
+/// round trip shows the latency saved by polling.
+pub fn measure_pingpong(iterations: u32, spin: Duration) -> io::Result<Measurement> {
+    let mut queue = EventQueue::<usize>::new().map_err(io_error)?;
+    queue.set_spin_before_block(spin);
+    let (mut ping_reader, mut ping_writer) = pipe()?;
+    let (mut pong_reader, mut pong_writer) = pipe()?;
+    queue
+        .subscribe(pong_reader.as_raw_fd() as usize, 0, EventFlags::READ)
+        .map_err(io_error)?;
+
+    let echo = thread::spawn(move || -> io::Result<()> {
+        let mut byte = [0];
+        for _ in 0..iterations {
+            ping_reader.read_exact(&mut byte)?;
+            pong_writer.write_all(&byte)?;
+        }
+        Ok(())
+    });
+
+    let mut measurement = Measurement::new();
+    for _ in 0..iterations {
+        let start = Instant::now();
+        ping_writer.write_all(&[0])?;
+        queue.next_event().map_err(io_error)?;
+        measurement.record(start.elapsed());
+
+        pong_reader.read_exact(&mut [0])?;
+    }
+    echo.join().expect("echo thread panicked")?;
+    Ok(measurement)
+}
+
+/// Measure subscribing to and unsubscribing from an fd
+pub fn measure_subscribe(iterations: u32) -> io::Result<Measurement> {
+    let queue = RawEventQueue::new().map_err(io_error)?;
+    let (reader, _writer) = pipe()?;
+    let fd = reader.as_raw_fd() as usize;
+
+    let mut measurement = Measurement::new();
+    for _ in 0..iterations {
+        let start = Instant::now();
+        queue.subscribe(fd, 0, EventFlags::READ).map_err(io_error)?;
+        queue.unsubscribe(fd).map_err(io_error)?;
+        measurement.record(start.elapsed());
+    }
+    Ok(measurement)
+}
+
+/// Measure reading the events of `batch` readable fds, one wait at a time
+pub fn measure_batch(batch: usize, iterations: u32) -> io::Result<Measurement> {
+    let mut events = Vec::<RawEvent>::with_capacity(batch);
+    measure_batch_with(batch, iterations, |queue| {
+        events.clear();
+        while events.len() < batch {
+            let remaining = batch - events.len();
+            queue.read_into_vec(&mut events, remaining)?;
+        }
+        Ok(())
+    })
+}
+
+/// Like [`measure_batch`], reading the events into an [`EventColumns`] instead
+pub fn measure_batch_columns(batch: usize, iterations: u32) -> io::Result<Measurement> {
+    let mut columns = EventColumns::with_capacity(batch);
+    measure_batch_with(batch, iterations, |queue| {
+        columns.clear();
+        while columns.len() < batch {
+            let remaining = batch - columns.len();
+            queue.read_into_columns(&mut columns, remaining)?;
+        }
+        Ok(())
+    })
+}
+
+// Time `read`, which must read the events of all `batch` fds, with every fd readable.
+fn measure_batch_with(
+    batch: usize,
+    iterations: u32,
+    mut read: impl FnMut(&RawEventQueue) -> libredox::error::Result<()>,
+) -> io::Result<Measurement> {
+    let queue = RawEventQueue::new().map_err(io_error)?;
+    let mut pipes = (0..batch)
+        .map(|_| pipe())
+        .collect::<io::Result<Vec<(PipeReader, PipeWriter)>>>()?;
+    for (i, (reader, _)) in pipes.iter().enumerate() {
+        queue
+            .subscribe(reader.as_raw_fd() as usize, i, EventFlags::READ)
+            .map_err(io_error)?;
+    }
+
+    let mut measurement = Measurement::new();
+    for _ in 0..iterations {
+        for (_, writer) in &mut pipes {
+            writer.write_all(&[0])?;
+        }
+
+        let start = Instant::now();
+        read(&queue).map_err(io_error)?;
+        measurement.record(start.elapsed());
+
+        for (reader, _) in &mut pipes {
+            reader.read_exact(&mut [0])?;
+        }
+    }
+    Ok(measurement)
+}
@@ -0,0 +1,157 @@
+//! C functions with epoll-like semantics, for C libraries implementing `epoll` and `poll`.
+//!
+//! All functions return a negated errno on failure, like raw syscalls.
+use core::mem::{ManuallyDrop, MaybeUninit};
+use core::time::Duration;
+
+use libredox::errno::EINVAL;
+use libredox::error::{Error, Result};
+
+use crate::{EventFlags, RawEvent, RawEventQueue, Timeout, WaitFlags};
+
+pub const REDOX_EVENT_IN: u32 = 0x001;
+pub const REDOX_EVENT_OUT: u32 = 0x004;
+
+pub const REDOX_EVENT_CTL_ADD: i32 = 1;
+pub const REDOX_EVENT_CTL_DEL: i32 = 2;
+pub const REDOX_EVENT_CTL_MOD: i32 = 3;
+
+/// An event, laid out like an unpacked `struct epoll_event`
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct RedoxEpollEvent {
+    /// `REDOX_EVENT_IN` and/or `REDOX_EVENT_OUT`
+    pub events: u32,
+    pub data: u64,
+}
+
+fn to_flags(events: u32) -> EventFlags {
+    let mut flags = EventFlags::empty();
+    flags.set(EventFlags::READ, events & REDOX_EVENT_IN != 0);
+    flags.set(EventFlags::WRITE, events & REDOX_EVENT_OUT != 0);
+    flags
+}
+fn from_flags(flags: EventFlags) -> u32 {
+    let mut events = 0;
+    if flags.contains(EventFlags::READ) {
+        events |= REDOX_EVENT_IN;
+    }
+    if flags.contains(EventFlags::WRITE) {
+        events |= REDOX_EVENT_OUT;
+    }
+    events
+}
+fn to_ret(res: Result<usize>) -> isize {
+    match res {
+        Ok(value) => value as isize,
+        Err(err) => -(err.errno() as isize),
+    }
+}
+// Operate on a queue owned by the caller, without closing it.
+fn borrow(queue: usize) -> ManuallyDrop<RawEventQueue> {
+    // SAFETY: The queue is never dropped, so ownership stays with the caller.
+    ManuallyDrop::new(unsafe { RawEventQueue::from_raw_fd(queue) })
+}
+
+/// Create an event queue, returning its fd
+#[no_mangle]
+pub extern "C" fn redox_event_capi_create() -> isize {
+    to_ret(RawEventQueue::new().map(RawEventQueue::into_raw_fd))
+}
+
+/// Add, modify or remove the subscription of `fd` to `queue`
+///
+/// Adding an fd that is already subscribed modifies its subscription, as the kernel makes no
+/// difference between the two. `event` is ignored when removing. Fails with `EINVAL` if the
+/// data of `event` does not fit in a `usize`.
+///
+/// # Safety
+///
+/// Unless `op` is `REDOX_EVENT_CTL_DEL`, `event` must point to a valid `RedoxEpollEvent`.
+#[no_mangle]
+pub unsafe extern "C" fn redox_event_capi_ctl(
+    queue: usize,
+    op: i32,
+    fd: usize,
+    event: *const RedoxEpollEvent,
+) -> isize {
+    let queue = borrow(queue);
+    to_ret(match op {
+        REDOX_EVENT_CTL_ADD | REDOX_EVENT_CTL_MOD if !event.is_null() => {
+            let event = unsafe { *event };
+            // Data that does not fit in the user data of a 32-bit queue cannot be returned intact.
+            match usize::try_from(event.data) {
+                Ok(data) => queue
+                    .subscribe(fd, data, to_flags(event.events))
+                    .map(|()| 0),
+                Err(_) => Err(Error::new(EINVAL)),
+            }
+        }
+        REDOX_EVENT_CTL_DEL => queue.unsubscribe(fd).map(|()| 0),
+        _ => Err(Error::new(EINVAL)),
+    })
+}
+
+/// Wait for up to `max_events` events, returning the number written to `events`
+///
+/// A negative `timeout_ms` waits forever, and zero does not wait at all. Returns zero if the
+/// timeout expired.
+///
+/// # Safety
+///
+/// `events` must be valid for writing `max_events` events.
+#[no_mangle]
+pub unsafe extern "C" fn redox_event_capi_wait(
+    queue: usize,
+    events: *mut RedoxEpollEvent,
+    max_events: i32,
+    timeout_ms: i32,
+) -> isize {
+    let Ok(max_events) = usize::try_from(max_events) else {
+        return to_ret(Err(Error::new(EINVAL)));
+    };
+    if max_events == 0 || events.is_null() {
+        return to_ret(Err(Error::new(EINVAL)));
+    }
+    let out = unsafe { core::slice::from_raw_parts_mut(events, max_events) };
+    let queue = borrow(queue);
+
+    let mut timeout = u64::try_from(timeout_ms)
+        .ok()
+        .map(|ms| Timeout::from(Duration::from_millis(ms)));
+    let mut buf = [MaybeUninit::<RawEvent>::uninit(); 16];
+    let mut count = 0;
+    while count < max_events {
+        let room = (max_events - count).min(buf.len());
+        let read = match queue.wait(&mut buf[..room], timeout, WaitFlags::NONE) {
+            Ok(read) => read,
+            // Report the events already read, the error will most likely happen again.
+            Err(_) if count > 0 => break,
+            Err(err) => return to_ret(Err(err)),
+        };
+        for raw in read.iter() {
+            out[count] = RedoxEpollEvent {
+                events: from_flags(EventFlags::from_bits_retain(raw.flags)),
+                data: raw.user_data as u64,
+            };
+            count += 1;
+        }
+        if read.len() < room {
+            break;
+        }
+        // Only the first wait blocks, the rest just collect what is already queued.
+        timeout = Some(Timeout::ZERO);
+    }
+    to_ret(Ok(count))
+}
+
+/// Close an event queue
+///
+/// # Safety
+///
+/// `queue` must be an event queue fd owned by the caller, which must not use it afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn redox_event_capi_destroy(queue: usize) -> isize {
+    let mut queue = unsafe { RawEventQueue::from_raw_fd(queue) };
+    to_ret(queue.shutdown().map(|()| 0))
+}
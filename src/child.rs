@@ -0,0 +1,165 @@
+//! Capturing the output of a child process through an event queue.
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::process::{Child, ChildStderr, ChildStdout, Command, ExitStatus, Stdio};
+use std::vec;
+use std::vec::Vec;
+
+use crate::wrappers::{io_error, set_nonblocking};
+use crate::{EventFlags, RawEventQueue};
+
+const STDOUT: usize = 0;
+const STDERR: usize = 1;
+
+/// Output or termination of a [`PipedChild`]
+#[derive(Debug)]
+pub enum ChildEvent {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    /// The child closed its output and has exited; no events follow
+    Exited(ExitStatus),
+}
+
+/// A child process whose stdout and stderr are read through an event queue
+///
+/// The queue can be embedded in another loop through [`as_raw_fd`](Self::as_raw_fd).
+pub struct PipedChild {
+    queue: RawEventQueue,
+    child: Child,
+    stdout: Option<ChildStdout>,
+    stderr: Option<ChildStderr>,
+
+    // Pipes that have not been read until they would block, and may have more data left.
+    ready: [bool; 2],
+    exited: bool,
+    buf: Vec<u8>,
+}
+
+impl PipedChild {
+    /// The maximum number of bytes delivered by a single event
+    pub const CHUNK_SIZE: usize = 4096;
+
+    /// Spawn `command` with its stdout and stderr piped and subscribed to
+    pub fn spawn(command: &mut Command) -> io::Result<Self> {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+
+        // The pipes are drained until they would block, which must not hang the caller.
+        for fd in stdout
+            .as_ref()
+            .map(AsRawFd::as_raw_fd)
+            .into_iter()
+            .chain(stderr.as_ref().map(AsRawFd::as_raw_fd))
+        {
+            set_nonblocking(fd as usize, true).map_err(io_error)?;
+        }
+
+        let queue = RawEventQueue::new().map_err(io_error)?;
+        if let Some(ref stdout) = stdout {
+            queue
+                .subscribe(stdout.as_raw_fd() as usize, STDOUT, EventFlags::READ)
+                .map_err(io_error)?;
+        }
+        if let Some(ref stderr) = stderr {
+            queue
+                .subscribe(stderr.as_raw_fd() as usize, STDERR, EventFlags::READ)
+                .map_err(io_error)?;
+        }
+
+        Ok(Self {
+            queue,
+            child,
+            stdout,
+            stderr,
+            ready: [false; 2],
+            exited: false,
+            buf: vec![0; Self::CHUNK_SIZE],
+        })
+    }
+    #[inline]
+    pub fn child(&self) -> &Child {
+        &self.child
+    }
+    #[inline]
+    pub fn child_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+    /// The file descriptor of the queue, which becomes readable when output is available
+    #[inline]
+    pub fn as_raw_fd(&self) -> usize {
+        self.queue.as_raw_fd()
+    }
+    /// Block until the child produces output or exits
+    ///
+    /// Returns `None` once [`ChildEvent::Exited`] has been returned.
+    pub fn next_event(&mut self) -> io::Result<Option<ChildEvent>> {
+        if self.exited {
+            return Ok(None);
+        }
+        loop {
+            if self.stdout.is_none() && self.stderr.is_none() {
+                self.exited = true;
+                return Ok(Some(ChildEvent::Exited(self.child.wait()?)));
+            }
+
+            let which = match self.ready.iter().position(|&ready| ready) {
+                Some(which) => which,
+                None => self.queue.next_event().map_err(io_error)?.user_data,
+            };
+            if let Some(event) = self.read(which)? {
+                return Ok(Some(event));
+            }
+        }
+    }
+    // Read a chunk from the pipe, closing it on EOF.
+    fn read(&mut self, which: usize) -> io::Result<Option<ChildEvent>> {
+        let pipe: Option<&mut dyn Read> = match which {
+            STDOUT => self.stdout.as_mut().map(|stdout| stdout as _),
+            _ => self.stderr.as_mut().map(|stderr| stderr as _),
+        };
+        let count = match pipe.map(|pipe| pipe.read(&mut self.buf)) {
+            Some(Ok(count)) => count,
+            Some(Err(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.ready[which] = false;
+                return Ok(None);
+            }
+            Some(Err(err)) => return Err(err),
+            None => 0,
+        };
+        self.ready[which] = count != 0;
+
+        if count == 0 {
+            self.close(which);
+            return Ok(None);
+        }
+        let buf = self.buf[..count].to_vec();
+        Ok(Some(match which {
+            STDOUT => ChildEvent::Stdout(buf),
+            _ => ChildEvent::Stderr(buf),
+        }))
+    }
+    fn close(&mut self, which: usize) {
+        let fd = match which {
+            STDOUT => self.stdout.as_ref().map(|stdout| stdout.as_raw_fd()),
+            _ => self.stderr.as_ref().map(|stderr| stderr.as_raw_fd()),
+        };
+        if let Some(fd) = fd {
+            let _ = self.queue.unsubscribe(fd as usize);
+        }
+        match which {
+            STDOUT => self.stdout = None,
+            _ => self.stderr = None,
+        }
+    }
+}
+impl Iterator for PipedChild {
+    type Item = io::Result<ChildEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
@@ -0,0 +1,58 @@
+//! An eventfd-like counter for waking up event queues.
+use std::io::{self, pipe, PipeReader, PipeWriter, Read, Write};
+use std::os::fd::AsRawFd;
+
+/// A counter that becomes readable when signaled, for use as a doorbell between threads or
+/// processes
+///
+/// This is the equivalent of Linux's `eventfd`. Redox has no kernel counter object, so it is
+/// emulated with a pipe carrying the signaled amounts, which are summed when consumed. Subscribe
+/// to [`as_raw_fd`](Self::as_raw_fd) with [`EventFlags::READ`](crate::EventFlags::READ) to be
+/// notified of signals.
+#[derive(Debug)]
+pub struct EventCounter {
+    reader: PipeReader,
+    writer: PipeWriter,
+}
+
+impl EventCounter {
+    // The number of signals read back at once.
+    const BATCH: usize = 64;
+
+    pub fn new() -> io::Result<Self> {
+        let (reader, writer) = pipe()?;
+        Ok(Self { reader, writer })
+    }
+    /// Add `n` to the counter, making it readable
+    pub fn signal(&self, n: u64) -> io::Result<()> {
+        // Writes this small are atomic, so concurrent signals never interleave.
+        (&self.writer).write_all(&n.to_ne_bytes())
+    }
+    /// Block until the counter has been signaled, and return its value, resetting it to zero
+    ///
+    /// If more signals are pending than can be read at once, the remaining ones are left for
+    /// the next call, and the counter stays readable.
+    pub fn consume(&self) -> io::Result<u64> {
+        let mut buf = [0; Self::BATCH * 8];
+        let mut len = (&self.reader).read(&mut buf)?;
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        // Signals are written whole, but might still be read partially.
+        if len % 8 != 0 {
+            let rest = 8 - len % 8;
+            (&self.reader).read_exact(&mut buf[len..len + rest])?;
+            len += rest;
+        }
+
+        Ok(buf[..len]
+            .chunks_exact(8)
+            .map(|n| u64::from_ne_bytes(n.try_into().unwrap()))
+            .fold(0, u64::saturating_add))
+    }
+    /// The file descriptor to subscribe to, which is readable while the counter is nonzero
+    #[inline]
+    pub fn as_raw_fd(&self) -> usize {
+        self.reader.as_raw_fd() as usize
+    }
+}
@@ -0,0 +1,81 @@
+//! Comparing the readiness of an fd with what its queue is about to report.
+use core::fmt;
+use core::time::Duration;
+
+use libredox::error::Result;
+
+use crate::{poll_many, EventFlags, EventQueue, UserData};
+
+/// The state of an fd as seen by the fd itself and by an [`EventQueue`]
+///
+/// Returned by [`EventQueue::diagnose`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Diagnosis {
+    pub fd: usize,
+    /// The interest of the subscription made through the queue, if any
+    pub subscribed: Option<EventFlags>,
+    /// The readiness of the fd, probed through a separate queue
+    pub ready: EventFlags,
+    /// The flags of the events for the fd that the queue holds but has not returned yet
+    pub held: EventFlags,
+}
+
+impl Diagnosis {
+    /// The subscribed readiness that the fd has, but that the queue holds no event for
+    ///
+    /// Readiness is only reported when it changes, so this is usually an fd that was not
+    /// drained after its last event was returned. Otherwise, the kernel or the scheme behind the
+    /// fd failed to post an event.
+    pub fn missing(&self) -> EventFlags {
+        self.ready & self.subscribed.unwrap_or_default() & !self.held
+    }
+    /// The held events for readiness that the fd no longer has
+    ///
+    /// This is expected when the fd was read or written since the event was posted, and handlers
+    /// should cope with it.
+    pub fn stale(&self) -> EventFlags {
+        self.held & !self.ready
+    }
+    /// Whether no subscribed readiness is [`missing`](Self::missing)
+    pub fn is_consistent(&self) -> bool {
+        self.missing().is_empty()
+    }
+}
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fd {}: ", self.fd)?;
+        match self.subscribed {
+            Some(flags) => write!(f, "subscribed={flags}")?,
+            None => write!(f, "not subscribed")?,
+        }
+        write!(f, " ready={} held={}", self.ready, self.held)?;
+        if !self.is_consistent() {
+            write!(f, ", missing events for {}", self.missing())?;
+        }
+        Ok(())
+    }
+}
+
+impl<U: UserData> EventQueue<U> {
+    /// Probe the readiness of `fd`, and compare it with the events this queue holds for it
+    ///
+    /// Every event the kernel has queued is read first, as by [`barrier`](Self::barrier), so
+    /// events posted before the call count as held. Only subscriptions made through this queue
+    /// are known to it.
+    pub fn diagnose(&self, fd: usize) -> Result<Diagnosis> {
+        self.barrier()?;
+        let ready = poll_many(
+            &[fd],
+            EventFlags::READ | EventFlags::WRITE,
+            Some(Duration::ZERO),
+        )?
+        .flags(0);
+        Ok(Diagnosis {
+            fd,
+            // Not `subscription`, which fails for user data that is not a valid U.
+            subscribed: self.subscribed_flags(fd),
+            ready,
+            held: self.held_flags(fd),
+        })
+    }
+}
@@ -0,0 +1,38 @@
+//! Draining readable fds, which only report readiness again once new data arrives.
+use core::ops::ControlFlow;
+use std::io::{self, ErrorKind, Read};
+
+/// How [`drain_readable`] stopped reading
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Drained {
+    /// All available data was read, and another event will be delivered when more arrives
+    WouldBlock,
+    /// The end of the stream was reached
+    Eof,
+    /// The callback stopped reading early, and data may be left that no event will report
+    Stopped,
+}
+
+/// Read from a nonblocking `reader` until it would block, passing each chunk read to `f`
+///
+/// An event is only delivered when an fd becomes readable, so a consumer that stops reading
+/// before draining it will not be woken up for the remaining data. If `f` breaks, the caller is
+/// responsible for coming back to it, as indicated by [`Drained::Stopped`].
+pub fn drain_readable(
+    mut reader: impl Read,
+    mut f: impl FnMut(&[u8]) -> ControlFlow<()>,
+) -> io::Result<Drained> {
+    let mut buf = [0; 4096];
+    loop {
+        let count = match reader.read(&mut buf) {
+            Ok(0) => return Ok(Drained::Eof),
+            Ok(count) => count,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(Drained::WouldBlock),
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if f(&buf[..count]).is_break() {
+            return Ok(Drained::Stopped);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Object-safe queue traits, for passing queues across trait object boundaries.
+//!
+//! [`EventQueueApi`](crate::EventQueueApi) is generic over the user data type, so a plugin taking
+//! one must agree on that type with its host. These traits deal in raw user data instead, so a
+//! host can hand any queue to a dynamically loaded driver as `&dyn EventSink`:
+//!
+//! ```ignore
+//! fn init(sink: &dyn EventSink) -> Result<()> {
+//!     sink.subscribe(irq_fd, IRQ_TOKEN, EventFlags::READ)
+//! }
+//! ```
+use libredox::errno::EINVAL;
+use libredox::error::{Error, Result};
+
+use crate::{EventFlags, EventQueue, RawEvent, RawEventQueue, Timeout, UserData};
+
+/// A queue that fds can be subscribed to
+pub trait EventSink {
+    /// Subscribe to events produced by `fd`, with raw user data
+    ///
+    /// Fails with `EINVAL` if the queue cannot represent `user_data`.
+    fn subscribe(&self, fd: usize, user_data: usize, flags: EventFlags) -> Result<()>;
+    /// Unsubscribe from events produced by `fd`
+    fn unsubscribe(&self, fd: usize) -> Result<()>;
+}
+
+/// A queue that can be waited on for events
+pub trait EventWaitable {
+    /// Block until the next event, or until `timeout` has passed if it is not `None`
+    fn wait_timeout(&self, timeout: Option<Timeout>) -> Result<Option<RawEvent>>;
+    /// The file descriptor of the queue, which becomes readable when events are pending
+    fn as_raw_fd(&self) -> usize;
+}
+
+impl EventSink for RawEventQueue {
+    #[inline]
+    fn subscribe(&self, fd: usize, user_data: usize, flags: EventFlags) -> Result<()> {
+        RawEventQueue::subscribe(self, fd, user_data, flags)
+    }
+    #[inline]
+    fn unsubscribe(&self, fd: usize) -> Result<()> {
+        RawEventQueue::unsubscribe(self, fd)
+    }
+}
+impl EventWaitable for RawEventQueue {
+    fn wait_timeout(&self, timeout: Option<Timeout>) -> Result<Option<RawEvent>> {
+        match timeout {
+            Some(timeout) => self.next_event_timeout(timeout),
+            None => self.next_event().map(Some),
+        }
+    }
+    #[inline]
+    fn as_raw_fd(&self) -> usize {
+        RawEventQueue::as_raw_fd(self)
+    }
+}
+
+impl<U: UserData> EventSink for EventQueue<U> {
+    fn subscribe(&self, fd: usize, user_data: usize, flags: EventFlags) -> Result<()> {
+        let data = U::try_from_user_data(user_data).ok_or(Error::new(EINVAL))?;
+        EventQueue::subscribe(self, fd, data, flags)
+    }
+    #[inline]
+    fn unsubscribe(&self, fd: usize) -> Result<()> {
+        EventQueue::unsubscribe(self, fd)
+    }
+}
+impl<U: UserData> EventWaitable for EventQueue<U> {
+    fn wait_timeout(&self, timeout: Option<Timeout>) -> Result<Option<RawEvent>> {
+        self.next_raw_event_timeout(timeout)
+    }
+    #[inline]
+    fn as_raw_fd(&self) -> usize {
+        EventQueue::as_raw_fd(self)
+    }
+}
@@ -0,0 +1,526 @@
+//! A callback-driven loop around an [`EventQueue`].
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+#[cfg(feature = "std")]
+use core::fmt;
+use core::mem;
+#[cfg(feature = "std")]
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use libredox::error::Result;
+
+use crate::{Event, EventFlags, EventQueue, RawEvent, UserData};
+
+type IdleCallback = Box<dyn FnMut() -> bool>;
+type DeferredAction<U> = Box<dyn FnOnce(&mut EventLoop<U>)>;
+type Continuation<U> = Box<dyn FnOnce(&Handle<U>, EventFlags)>;
+#[cfg(feature = "std")]
+type StallHook = Box<dyn FnMut(Stall)>;
+
+/// Passed to the event callback of an [`EventLoop`], for scheduling changes to the loop itself
+pub struct Handle<U: UserData> {
+    deferred: RefCell<VecDeque<DeferredAction<U>>>,
+    awaiting: RefCell<Vec<(usize, EventFlags, Continuation<U>)>>,
+}
+
+impl<U: UserData> Handle<U> {
+    /// Run `action` on the loop once the current batch of events has been dispatched
+    ///
+    /// This lets callbacks make changes that need the loop mutably, such as unsubscribing other
+    /// fds or dropping state shared with other callbacks. Actions run in the order they were
+    /// deferred, including those deferred by other actions.
+    pub fn defer(&self, action: impl FnOnce(&mut EventLoop<U>) + 'static) {
+        self.deferred.borrow_mut().push_back(Box::new(action));
+    }
+    /// Call `continuation` instead of the event callback once `fd` is readable
+    ///
+    /// See [`EventLoop::await_fd`]. The wait starts once the current batch of events has been
+    /// dispatched.
+    pub fn await_readable(
+        &self,
+        fd: usize,
+        continuation: impl FnOnce(&Handle<U>, EventFlags) + 'static,
+    ) {
+        self.await_fd(fd, EventFlags::READ, continuation)
+    }
+    /// Call `continuation` instead of the event callback once `fd` is writable
+    ///
+    /// See [`EventLoop::await_fd`]. The wait starts once the current batch of events has been
+    /// dispatched.
+    pub fn await_writable(
+        &self,
+        fd: usize,
+        continuation: impl FnOnce(&Handle<U>, EventFlags) + 'static,
+    ) {
+        self.await_fd(fd, EventFlags::WRITE, continuation)
+    }
+    fn await_fd(
+        &self,
+        fd: usize,
+        flags: EventFlags,
+        continuation: impl FnOnce(&Handle<U>, EventFlags) + 'static,
+    ) {
+        self.awaiting
+            .borrow_mut()
+            .push((fd, flags, Box::new(continuation)));
+    }
+}
+
+// A continuation waiting for an fd, in place of its regular subscription.
+struct Waiter<U: UserData> {
+    continuation: Continuation<U>,
+}
+
+// The state touched while dispatching a single event.
+struct Dispatcher<U: UserData> {
+    waiters: BTreeMap<usize, Waiter<U>>,
+
+    #[cfg(feature = "std")]
+    metrics: Option<Metrics>,
+    #[cfg(feature = "std")]
+    watchdog: Option<Watchdog>,
+}
+
+impl<U: UserData> Dispatcher<U> {
+    fn dispatch(
+        &mut self,
+        queue: &EventQueue<U>,
+        handle: &Handle<U>,
+        f: &mut impl FnMut(&Handle<U>, Event<U>),
+        raw: RawEvent,
+    ) -> Result<()> {
+        // Intercepted before conversion, as the placeholder user data need not be a valid U.
+        if let Some(waiter) = self.waiters.remove(&raw.fd) {
+            queue.restore(raw.fd)?;
+            (waiter.continuation)(handle, EventFlags::from_bits_retain(raw.flags));
+            return Ok(());
+        }
+        let event = EventQueue::<U>::event_from_raw(raw)?;
+
+        #[cfg(feature = "std")]
+        if self.metrics.is_some() || self.watchdog.is_some() {
+            let user_data = event.user_data.into_user_data();
+            let start = Instant::now();
+            f(handle, event);
+            let elapsed = start.elapsed();
+
+            if let Some(ref mut metrics) = self.metrics {
+                metrics.record(user_data, elapsed);
+            }
+            if let Some(ref mut watchdog) = self.watchdog {
+                if elapsed > watchdog.callback_limit {
+                    (watchdog.hook)(Stall::Callback { user_data, elapsed });
+                }
+            }
+            return Ok(());
+        }
+        f(handle, event);
+        Ok(())
+    }
+}
+
+/// Dispatch statistics of the events with a given user data
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SourceMetrics {
+    /// The number of events dispatched
+    pub dispatched: u64,
+    /// The total time spent in the callback for these events
+    pub total_time: Duration,
+    /// The longest time spent in the callback for a single event
+    pub max_latency: Duration,
+}
+
+/// Dispatch statistics of an [`EventLoop`], keyed by user data
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    sources: BTreeMap<usize, SourceMetrics>,
+}
+
+#[cfg(feature = "std")]
+impl Metrics {
+    /// The statistics of events with `data` as their user data, if any were dispatched
+    pub fn get<U: UserData>(&self, data: U) -> Option<&SourceMetrics> {
+        self.sources.get(&data.into_user_data())
+    }
+    /// Iterate over the statistics of every source, by raw user data
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &SourceMetrics)> + '_ {
+        self.sources
+            .iter()
+            .map(|(&user_data, metrics)| (user_data, metrics))
+    }
+    /// Write a human-readable table of the statistics to `w`, e.g. from a diagnostic signal
+    pub fn dump(&self, mut w: impl fmt::Write) -> fmt::Result {
+        for (user_data, metrics) in self.iter() {
+            writeln!(
+                w,
+                "user_data={:#x}: dispatched={} total={:?} max={:?}",
+                user_data, metrics.dispatched, metrics.total_time, metrics.max_latency
+            )?;
+        }
+        Ok(())
+    }
+    fn record(&mut self, user_data: usize, elapsed: Duration) {
+        let metrics = self.sources.entry(user_data).or_default();
+        metrics.dispatched += 1;
+        metrics.total_time += elapsed;
+        metrics.max_latency = metrics.max_latency.max(elapsed);
+    }
+}
+
+/// A stall detected by the watchdog of an [`EventLoop`]
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stall {
+    /// The callback for an event with `user_data` ran for `elapsed`
+    Callback { user_data: usize, elapsed: Duration },
+    /// The loop went `elapsed` without waiting for events
+    Loop { elapsed: Duration },
+}
+
+#[cfg(feature = "std")]
+struct Watchdog {
+    callback_limit: Duration,
+    loop_limit: Duration,
+    hook: StallHook,
+}
+
+/// The dispatch class of an fd in an [`EventLoop`]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Priority {
+    /// Command channels such as shutdown or configuration requests, dispatched first
+    Control,
+    /// Regular traffic
+    #[default]
+    Data,
+}
+
+/// An [`EventQueue`] that dispatches events to a callback, running idle work in between
+pub struct EventLoop<U: UserData> {
+    queue: EventQueue<U>,
+    handle: Handle<U>,
+    // Fds with Priority::Control.
+    control: BTreeSet<usize>,
+    // The events of a batch while sorting them by priority, kept to reuse the allocation.
+    ready: Vec<RawEvent>,
+
+    idle: Option<IdleCallback>,
+    // Whether the idle callback reported having more work the last time it ran.
+    idle_pending: bool,
+    max_idle_batch: usize,
+    max_dispatch_batch: usize,
+
+    dispatcher: Dispatcher<U>,
+}
+
+impl<U: UserData> EventLoop<U> {
+    /// The default number of times the idle callback is run before checking for events again
+    pub const DEFAULT_MAX_IDLE_BATCH: usize = 16;
+    /// The default number of events dispatched by a single [`run_once`](Self::run_once)
+    pub const DEFAULT_MAX_DISPATCH_BATCH: usize = 64;
+
+    /// Create a loop around a new event queue
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_queue(EventQueue::new()?))
+    }
+    /// Create a loop around an existing event queue
+    pub fn with_queue(queue: EventQueue<U>) -> Self {
+        Self {
+            queue,
+            handle: Handle {
+                deferred: RefCell::new(VecDeque::new()),
+                awaiting: RefCell::new(Vec::new()),
+            },
+            control: BTreeSet::new(),
+            ready: Vec::new(),
+            idle: None,
+            idle_pending: false,
+            max_idle_batch: Self::DEFAULT_MAX_IDLE_BATCH,
+            max_dispatch_batch: Self::DEFAULT_MAX_DISPATCH_BATCH,
+            dispatcher: Dispatcher {
+                waiters: BTreeMap::new(),
+                #[cfg(feature = "std")]
+                metrics: None,
+                #[cfg(feature = "std")]
+                watchdog: None,
+            },
+        }
+    }
+    #[inline]
+    pub fn queue(&self) -> &EventQueue<U> {
+        &self.queue
+    }
+    #[inline]
+    pub fn queue_mut(&mut self) -> &mut EventQueue<U> {
+        &mut self.queue
+    }
+    /// The handle passed to the event callback, for deferring actions from outside of it
+    #[inline]
+    pub fn handle(&self) -> &Handle<U> {
+        &self.handle
+    }
+    /// Call `callback` when the loop would otherwise block, replacing any previous idle callback
+    ///
+    /// The callback returns whether it has more work left. While it does, the loop keeps calling
+    /// it instead of blocking, up to the [max idle batch](Self::set_max_idle_batch) at a time.
+    /// Once it returns false, it is not called again until more events have been dispatched.
+    pub fn on_idle(&mut self, callback: impl FnMut() -> bool + 'static) {
+        self.idle = Some(Box::new(callback));
+        self.idle_pending = true;
+    }
+    /// Remove the callback set by [`on_idle`](Self::on_idle), if any
+    pub fn clear_idle(&mut self) {
+        self.idle = None;
+        self.idle_pending = false;
+    }
+    /// Set how many times the idle callback may run before checking for events again
+    ///
+    /// This bounds the latency added to events arriving while idle work is being done.
+    pub fn set_max_idle_batch(&mut self, max: usize) {
+        self.max_idle_batch = max.max(1);
+    }
+    /// Set how many events may be dispatched before deferred actions and waits are handled
+    ///
+    /// Events left over stay queued for the next [`run_once`](Self::run_once), so a busy fd
+    /// cannot hold back the rest of the loop.
+    pub fn set_max_dispatch_batch(&mut self, max: usize) {
+        self.max_dispatch_batch = max.max(1);
+    }
+    /// Set the dispatch class of `fd`, which is [`Priority::Data`] by default
+    ///
+    /// Within each batch of ready events, those for control fds are dispatched before all
+    /// others, so commands are never stuck behind bulk traffic. Otherwise events are dispatched
+    /// in the order they arrived.
+    pub fn set_priority(&mut self, fd: usize, priority: Priority) {
+        match priority {
+            Priority::Control => self.control.insert(fd),
+            Priority::Data => self.control.remove(&fd),
+        };
+    }
+    /// Start collecting dispatch statistics, which are off by default
+    #[cfg(feature = "std")]
+    pub fn enable_metrics(&mut self) {
+        self.dispatcher.metrics.get_or_insert_with(Metrics::default);
+    }
+    /// Stop collecting dispatch statistics, discarding those collected so far
+    #[cfg(feature = "std")]
+    pub fn disable_metrics(&mut self) {
+        self.dispatcher.metrics = None;
+    }
+    /// The dispatch statistics collected since [`enable_metrics`](Self::enable_metrics)
+    #[cfg(feature = "std")]
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.dispatcher.metrics.as_ref()
+    }
+    /// Call `hook` when a callback runs for longer than `callback_limit`, or the loop runs for
+    /// longer than `loop_limit` without waiting for events
+    ///
+    /// Stalls are detected once they are over, when the callback returns or the loop is about to
+    /// wait, so the hook runs on the loop thread and can log, count, or abort. The loop time
+    /// covers dispatching, idle work and deferred actions. This replaces any previous watchdog.
+    #[cfg(feature = "std")]
+    pub fn set_watchdog(
+        &mut self,
+        callback_limit: Duration,
+        loop_limit: Duration,
+        hook: impl FnMut(Stall) + 'static,
+    ) {
+        self.dispatcher.watchdog = Some(Watchdog {
+            callback_limit,
+            loop_limit,
+            hook: Box::new(hook),
+        });
+    }
+    /// Remove the watchdog set by [`set_watchdog`](Self::set_watchdog), if any
+    #[cfg(feature = "std")]
+    pub fn clear_watchdog(&mut self) {
+        self.dispatcher.watchdog = None;
+    }
+    /// Call `continuation` instead of the event callback once `fd` is ready for `flags`
+    ///
+    /// This lets a handler wait for another fd in the middle of a sequential protocol, without
+    /// blocking the loop. While waiting, the kernel reports `flags` in place of any subscription of
+    /// `fd` made through the queue, which is restored as it was, interests and nonblocking mode
+    /// included, before the continuation is called. Only one continuation can
+    /// wait for a given fd at a time, so this replaces any previous one.
+    ///
+    /// The event for `fd` is intercepted before its user data is converted to `U`, so `fd` need
+    /// not have been subscribed before.
+    pub fn await_fd(
+        &mut self,
+        fd: usize,
+        flags: EventFlags,
+        continuation: impl FnOnce(&Handle<U>, EventFlags) + 'static,
+    ) -> Result<()> {
+        self.await_boxed(fd, flags, Box::new(continuation))
+    }
+    fn await_boxed(
+        &mut self,
+        fd: usize,
+        flags: EventFlags,
+        continuation: Continuation<U>,
+    ) -> Result<()> {
+        // The subscription stays in the queue's table, interests and nonblocking mode included, and
+        // is put back in the kernel once the wait completes.
+        if let Err(err) = self.queue.retarget(fd, flags) {
+            if self.dispatcher.waiters.remove(&fd).is_some() {
+                let _ = self.queue.restore(fd);
+            }
+            return Err(err);
+        }
+        self.dispatcher.waiters.insert(fd, Waiter { continuation });
+        Ok(())
+    }
+    /// Run a single iteration of the loop, passing every ready event to `f`
+    ///
+    /// If no events are ready, pending idle work is done first, and the loop only blocks for the
+    /// next event once there is none left. At most the [max dispatch
+    /// batch](Self::set_max_dispatch_batch) of events is dispatched. Returns the number of events
+    /// dispatched, which is zero if only idle work was done.
+    ///
+    /// Waits started and actions deferred through the [`Handle`] take effect after the events
+    /// have been dispatched. A wait that cannot be started is dropped and its error returned,
+    /// after the other waits and deferred actions have been handled.
+    pub fn run_once(&mut self, mut f: impl FnMut(&Handle<U>, Event<U>)) -> Result<usize> {
+        #[cfg(feature = "std")]
+        let mut awake = self.dispatcher.watchdog.is_some().then(Instant::now);
+
+        let mut count = self.dispatch_ready(&mut f, None)?;
+        if count == 0 {
+            if self.run_idle() {
+                #[cfg(feature = "std")]
+                self.check_loop(awake);
+                return Ok(0);
+            }
+            let event = self.queue.next_raw_event()?;
+            #[cfg(feature = "std")]
+            {
+                awake = self.dispatcher.watchdog.is_some().then(Instant::now);
+            }
+            count = self.dispatch_ready(&mut f, Some(event))?;
+        }
+        let awaited = self.start_awaiting();
+        self.run_deferred();
+        awaited?;
+
+        // Handling events may have left work behind for the idle callback.
+        self.idle_pending = self.idle.is_some();
+        #[cfg(feature = "std")]
+        self.check_loop(awake);
+        Ok(count)
+    }
+    // Report a stall if the loop has been running since `awake` for too long.
+    #[cfg(feature = "std")]
+    fn check_loop(&mut self, awake: Option<Instant>) {
+        let (Some(awake), Some(watchdog)) = (awake, &mut self.dispatcher.watchdog) else {
+            return;
+        };
+        let elapsed = awake.elapsed();
+        if elapsed > watchdog.loop_limit {
+            (watchdog.hook)(Stall::Loop { elapsed });
+        }
+    }
+    // Dispatch `first`, if any, followed by every event that is ready.
+    fn dispatch_ready(
+        &mut self,
+        f: &mut impl FnMut(&Handle<U>, Event<U>),
+        first: Option<RawEvent>,
+    ) -> Result<usize> {
+        let Self {
+            queue,
+            handle,
+            control,
+            ready,
+            dispatcher,
+            max_dispatch_batch,
+            ..
+        } = self;
+        let max = *max_dispatch_batch;
+
+        if control.is_empty() {
+            let mut res = Ok(());
+            let mut count = 0;
+            if let Some(raw) = first {
+                res = dispatcher.dispatch(queue, handle, f, raw);
+                count += 1;
+            }
+            while count < max {
+                let Some(raw) = queue.try_next_raw_event()? else {
+                    break;
+                };
+                let event_res = dispatcher.dispatch(queue, handle, f, raw);
+                if res.is_ok() {
+                    res = event_res;
+                }
+                count += 1;
+            }
+            return res.map(|()| count);
+        }
+
+        ready.extend(first);
+        let mut res = loop {
+            if ready.len() >= max {
+                break Ok(());
+            }
+            match queue.try_next_raw_event() {
+                Ok(Some(raw)) => ready.push(raw),
+                Ok(None) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+        let count = ready.len();
+
+        for &raw in ready.iter().filter(|raw| control.contains(&raw.fd)) {
+            let event_res = dispatcher.dispatch(queue, handle, f, raw);
+            if res.is_ok() {
+                res = event_res;
+            }
+        }
+        for raw in ready.drain(..).filter(|raw| !control.contains(&raw.fd)) {
+            let event_res = dispatcher.dispatch(queue, handle, f, raw);
+            if res.is_ok() {
+                res = event_res;
+            }
+        }
+        res.map(|()| count)
+    }
+    fn start_awaiting(&mut self) -> Result<()> {
+        let awaiting = mem::take(&mut *self.handle.awaiting.borrow_mut());
+        let mut res = Ok(());
+        for (fd, flags, continuation) in awaiting {
+            let await_res = self.await_boxed(fd, flags, continuation);
+            if res.is_ok() {
+                res = await_res;
+            }
+        }
+        res
+    }
+    fn run_deferred(&mut self) {
+        loop {
+            // The borrow must end before running the action, which may defer more.
+            let action = self.handle.deferred.borrow_mut().pop_front();
+            let Some(action) = action else {
+                break;
+            };
+            action(self);
+        }
+    }
+    // Returns whether the idle callback still has work left.
+    fn run_idle(&mut self) -> bool {
+        let Some(ref mut idle) = self.idle else {
+            return false;
+        };
+        for _ in 0..self.max_idle_batch {
+            if !self.idle_pending {
+                break;
+            }
+            self.idle_pending = idle();
+        }
+        self.idle_pending
+    }
+}
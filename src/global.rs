@@ -0,0 +1,26 @@
+//! A queue shared by the whole process.
+use std::sync::OnceLock;
+
+use libredox::error::Result;
+
+use crate::RawEventQueue;
+
+static GLOBAL: OnceLock<RawEventQueue> = OnceLock::new();
+
+/// The process-wide queue, created on first use
+///
+/// This lets small utilities and libraries subscribe fds without being passed a queue. It is a
+/// [`RawEventQueue`] rather than an [`EventQueue`](crate::EventQueue), as only the former can be
+/// shared between threads, so the user data of its subscriptions is a plain `usize`. Libraries
+/// sharing it must agree on how user data is allocated, for example by using their fds.
+///
+/// The queue is never destroyed. If creating it fails, the next call tries again.
+pub fn global() -> Result<&'static RawEventQueue> {
+    if let Some(queue) = GLOBAL.get() {
+        return Ok(queue);
+    }
+    let queue = RawEventQueue::new()?;
+    // Another thread may have won the race, in which case this queue is dropped.
+    let _ = GLOBAL.set(queue);
+    Ok(GLOBAL.get().expect("the global queue was just set"))
+}
@@ -0,0 +1,76 @@
+//! A record of the last events delivered by a queue, for post-mortem debugging.
+use std::collections::VecDeque;
+use std::io::Write;
+use std::thread;
+use std::time::Instant;
+use std::vec::Vec;
+
+use crate::{EventFlags, RawEvent};
+
+/// An event delivered by an [`EventQueue`](crate::EventQueue) created
+/// [`with_history`](crate::EventQueue::with_history)
+#[derive(Clone, Copy, Debug)]
+pub struct HistoryEntry {
+    /// When the event was delivered
+    pub at: Instant,
+    pub event: RawEvent,
+}
+
+/// A ring of the last `capacity` delivered events, written to stderr if dropped while panicking
+pub(crate) struct History {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+    pub(crate) fn record(&mut self, event: RawEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(HistoryEntry {
+            at: Instant::now(),
+            event,
+        });
+    }
+    pub(crate) fn entries(&self) -> Vec<HistoryEntry> {
+        self.entries.iter().copied().collect()
+    }
+    pub(crate) fn dump(&self, mut w: impl core::fmt::Write) -> core::fmt::Result {
+        let now = Instant::now();
+        writeln!(w, "  last {} events, oldest first:", self.entries.len())?;
+        for entry in &self.entries {
+            writeln!(
+                w,
+                "    {:?} ago: fd {}: user_data={:#x} flags={:?}",
+                now.saturating_duration_since(entry.at),
+                entry.event.fd,
+                entry.event.user_data,
+                EventFlags::from_bits_retain(entry.event.flags)
+            )?;
+        }
+        Ok(())
+    }
+}
+impl Drop for History {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+        let mut out = std::string::String::new();
+        if self.dump(&mut out).is_ok() {
+            let _ = write!(
+                std::io::stderr(),
+                "event queue dropped while panicking\n{out}"
+            );
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Interrupting a blocked wait, including from a signal handler.
+use std::io::{self, pipe, PipeReader, Read};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::sync::Arc;
+
+use libredox::errno::EIO;
+use libredox::error::{Error, Result};
+
+use crate::wrappers::set_nonblocking;
+
+/// Makes a wait on an [`EventQueue`](crate::EventQueue) return `EINTR`
+///
+/// The handle shares ownership of the write end of the queue's interrupt pipe, so it stays valid
+/// after the queue is dropped, where interrupting no longer has any effect. See
+/// [`EventQueue::interrupt_handle`](crate::EventQueue::interrupt_handle).
+#[derive(Clone, Debug)]
+pub struct InterruptHandle {
+    writer: Arc<OwnedFd>,
+}
+
+impl InterruptHandle {
+    /// Make the current or next wait of the queue fail with `EINTR`
+    ///
+    /// This only makes a single nonblocking write, and neither allocates nor locks, so it is safe
+    /// to call from a signal handler. Interrupts that arrive before the queue waits again are
+    /// coalesced.
+    pub fn interrupt(&self) {
+        // A full pipe already holds an interrupt.
+        let _ = libredox::call::write(self.writer.as_raw_fd() as usize, &[0]);
+    }
+}
+
+// The pipe behind an InterruptHandle, whose read end is subscribed in the queue.
+#[derive(Debug)]
+pub(crate) struct Interrupter {
+    reader: PipeReader,
+    writer: Arc<OwnedFd>,
+}
+
+impl Interrupter {
+    pub(crate) fn new() -> Result<Self> {
+        let (reader, writer) =
+            pipe().map_err(|err| Error::new(err.raw_os_error().unwrap_or(EIO)))?;
+        set_nonblocking(reader.as_raw_fd() as usize, true)?;
+        set_nonblocking(writer.as_raw_fd() as usize, true)?;
+        Ok(Self {
+            reader,
+            writer: Arc::new(writer.into()),
+        })
+    }
+    pub(crate) fn fd(&self) -> usize {
+        self.reader.as_raw_fd() as usize
+    }
+    pub(crate) fn handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            writer: Arc::clone(&self.writer),
+        }
+    }
+    // Consume every pending interrupt.
+    pub(crate) fn clear(&self) {
+        let mut buf = [0; 64];
+        loop {
+            match (&self.reader).read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => continue,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! A registry of subscriptions that keeps per-fd state without allocating.
+//!
+//! The state of each subscription lives in a [`Node`] owned by the caller, typically as a field
+//! of a driver's own structures, and is linked into the [`Registry`] when registered. Nodes must
+//! outlive the registry, which the borrow checker enforces, so events can be mapped back to
+//! their node without any unsafe code or heap allocation:
+//!
+//! ```ignore
+//! let irq = Node::new(irq_fd, IrqState::default());
+//! let registry = Registry::new()?;
+//! registry.register(&irq, EventFlags::READ)?;
+//!
+//! let (node, flags) = registry.next_event()?;
+//! node.data().handle(flags);
+//! ```
+use core::cell::Cell;
+use core::ptr;
+
+use libredox::errno::{EBUSY, EEXIST, ENOENT};
+use libredox::error::{Error, Result};
+
+use crate::{EventFlags, RawEventQueue};
+
+/// Caller-owned state of a subscription in a [`Registry`]
+///
+/// Use interior mutability in `T` for state that is changed when handling events. A node can only
+/// be registered in one registry at a time.
+pub struct Node<'n, T> {
+    fd: usize,
+    data: T,
+    next: Cell<Option<&'n Node<'n, T>>>,
+    linked: Cell<bool>,
+}
+
+impl<'n, T> Node<'n, T> {
+    pub const fn new(fd: usize, data: T) -> Self {
+        Self {
+            fd,
+            data,
+            next: Cell::new(None),
+            linked: Cell::new(false),
+        }
+    }
+    #[inline]
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+    #[inline]
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+    /// Whether the node is currently registered
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        self.linked.get()
+    }
+}
+
+/// An event queue whose subscriptions are [`Node`]s linked into an intrusive list
+pub struct Registry<'n, T> {
+    inner: RawEventQueue,
+    head: Cell<Option<&'n Node<'n, T>>>,
+}
+
+impl<'n, T> Registry<'n, T> {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            inner: RawEventQueue::new()?,
+            head: Cell::new(None),
+        })
+    }
+    /// Subscribe to events for the fd of `node`, or change the flags if it is already registered
+    ///
+    /// Fails with `EEXIST` if another node with the same fd is registered, and with `EBUSY` if
+    /// `node` is registered in another registry.
+    pub fn register(&self, node: &'n Node<'n, T>, flags: EventFlags) -> Result<()> {
+        match self.find(node.fd) {
+            Some(other) if !ptr::eq(other, node) => return Err(Error::new(EEXIST)),
+            None if node.linked.get() => return Err(Error::new(EBUSY)),
+            _ => (),
+        }
+        self.inner.subscribe(node.fd, node.fd, flags)?;
+
+        if !node.linked.replace(true) {
+            node.next.set(self.head.get());
+            self.head.set(Some(node));
+        }
+        Ok(())
+    }
+    /// Unsubscribe from the fd of `node`, and unlink it
+    ///
+    /// Fails with `ENOENT` if `node` is not registered in this registry.
+    pub fn unregister(&self, node: &'n Node<'n, T>) -> Result<()> {
+        if !self.find(node.fd).is_some_and(|other| ptr::eq(other, node)) {
+            return Err(Error::new(ENOENT));
+        }
+        self.inner.unsubscribe(node.fd)?;
+
+        let mut link = &self.head;
+        while let Some(current) = link.get() {
+            if ptr::eq(current, node) {
+                link.set(node.next.take());
+                break;
+            }
+            link = &current.next;
+        }
+        node.linked.set(false);
+        Ok(())
+    }
+    fn find(&self, fd: usize) -> Option<&'n Node<'n, T>> {
+        let mut current = self.head.get();
+        while let Some(node) = current {
+            if node.fd == fd {
+                return Some(node);
+            }
+            current = node.next.get();
+        }
+        None
+    }
+    /// Block until the next event, returning the node it is for
+    ///
+    /// Events for nodes that were unregistered in the meantime are skipped.
+    pub fn next_event(&self) -> Result<(&'n Node<'n, T>, EventFlags)> {
+        loop {
+            let raw = self.inner.next_event()?;
+            if let Some(node) = self.find(raw.user_data) {
+                return Ok((node, EventFlags::from_bits_retain(raw.flags)));
+            }
+        }
+    }
+    /// Return the next event if one is already available, without blocking
+    pub fn try_next_event(&self) -> Result<Option<(&'n Node<'n, T>, EventFlags)>> {
+        while let Some(raw) = self.inner.try_next_event()? {
+            if let Some(node) = self.find(raw.user_data) {
+                return Ok(Some((node, EventFlags::from_bits_retain(raw.flags))));
+            }
+        }
+        Ok(None)
+    }
+    #[inline]
+    pub fn as_raw_fd(&self) -> usize {
+        self.inner.as_raw_fd()
+    }
+}
@@ -0,0 +1,67 @@
+//! Tracking of live queues and their subscriptions, for finding leaks.
+use core::fmt;
+use core::panic::Location;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, PoisonError};
+
+use crate::EventFlags;
+
+struct QueueInfo {
+    created_at: &'static Location<'static>,
+    subscriptions: BTreeSet<usize>,
+}
+
+static QUEUES: Mutex<BTreeMap<usize, QueueInfo>> = Mutex::new(BTreeMap::new());
+
+fn with_queues<R>(f: impl FnOnce(&mut BTreeMap<usize, QueueInfo>) -> R) -> R {
+    f(&mut QUEUES.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+pub(crate) fn created(queue: usize, created_at: &'static Location<'static>) {
+    with_queues(|queues| {
+        queues.insert(
+            queue,
+            QueueInfo {
+                created_at,
+                subscriptions: BTreeSet::new(),
+            },
+        )
+    });
+}
+pub(crate) fn destroyed(queue: usize) {
+    with_queues(|queues| queues.remove(&queue));
+}
+pub(crate) fn subscribed(queue: usize, fd: usize, flags: EventFlags) {
+    with_queues(|queues| {
+        let Some(info) = queues.get_mut(&queue) else {
+            return;
+        };
+        if flags.is_empty() {
+            info.subscriptions.remove(&fd);
+        } else {
+            info.subscriptions.insert(fd);
+        }
+    });
+}
+
+/// Write every queue that is still open, where it was created and the fds it is subscribed to
+///
+/// Only available with the `leak-check` feature. Queues adopted with
+/// [`from_raw_fd`](crate::RawEventQueue::from_raw_fd) are not tracked. Calling this before a
+/// daemon exits, or periodically, shows queues that were never dropped, and queues whose
+/// subscriptions keep growing. Returns the number of open queues.
+pub fn report_leaks(mut w: impl fmt::Write) -> Result<usize, fmt::Error> {
+    with_queues(|queues| {
+        for (queue, info) in queues.iter() {
+            writeln!(
+                w,
+                "event queue {} created at {}: {} subscriptions {:?}",
+                queue,
+                info.created_at,
+                info.subscriptions.len(),
+                info.subscriptions
+            )?;
+        }
+        Ok(queues.len())
+    })
+}
@@ -1,11 +1,16 @@
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
 #[cfg(feature = "wrappers")]
 pub extern crate libredox;
 
 #[cfg(feature = "redox_syscall")]
 mod sc {
-    use crate::raw::EventFlags;
+    use crate::raw::{EventFlags, RawEventV1};
 
     const _: () = {
         if EventFlags::READ.bits() as usize != syscall::EventFlags::EVENT_READ.bits() {
@@ -15,6 +20,9 @@ mod sc {
             panic!();
         }
     };
+    // Only the flags known to both sides are converted. Any other bit, including unknown bits
+    // retained by `from_bits_retain`, is dropped rather than being passed through with a meaning
+    // the other side may not share.
     impl From<syscall::flag::EventFlags> for EventFlags {
         fn from(value: syscall::flag::EventFlags) -> Self {
             let mut this = Self::empty();
@@ -34,10 +42,228 @@ mod sc {
             this
         }
     }
+
+    // Events as read from an `event:` scheme fd by older code.
+    impl From<syscall::data::Event> for RawEventV1 {
+        fn from(value: syscall::data::Event) -> Self {
+            Self {
+                fd: value.id,
+                user_data: value.data,
+                flags: EventFlags::from(value.flags).bits(),
+            }
+        }
+    }
+    impl From<RawEventV1> for syscall::data::Event {
+        fn from(value: RawEventV1) -> Self {
+            Self {
+                id: value.fd,
+                flags: EventFlags::from_bits_retain(value.flags).into(),
+                data: value.user_data,
+            }
+        }
+    }
+
+    // The user data of a legacy event is only converted if it is a valid `U`, as when reading
+    // from a queue.
+    #[cfg(feature = "wrappers")]
+    impl<U: crate::UserData> TryFrom<syscall::data::Event> for crate::Event<U> {
+        type Error = libredox::error::Error;
+
+        fn try_from(value: syscall::data::Event) -> Result<Self, Self::Error> {
+            let user_data = U::try_from_user_data(value.data)
+                .ok_or(libredox::error::Error::new(libredox::errno::EINVAL))?;
+            Ok(Self {
+                user_data,
+                flags: value.flags.into(),
+                fd: value.id,
+            })
+        }
+    }
+    #[cfg(feature = "wrappers")]
+    impl<U: crate::UserData> From<crate::Event<U>> for syscall::data::Event {
+        fn from(value: crate::Event<U>) -> Self {
+            Self {
+                id: value.fd,
+                flags: value.flags.into(),
+                data: value.user_data.into_user_data(),
+            }
+        }
+    }
 }
 pub mod raw;
 
+#[cfg(feature = "debug-rt")]
+pub mod rt;
+
 #[cfg(feature = "wrappers")]
 mod wrappers;
 #[cfg(feature = "wrappers")]
 pub use wrappers::*;
+
+#[cfg(feature = "leak-check")]
+mod leak;
+#[cfg(feature = "leak-check")]
+pub use leak::report_leaks;
+
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "stats")]
+pub use stats::SyscallStats;
+
+#[cfg(feature = "wrappers")]
+mod pipeline;
+#[cfg(feature = "wrappers")]
+pub use pipeline::{EventQueueExt, FilterEvents, InspectEvents, MapEvents};
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod columns;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use columns::EventColumns;
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod diagnose;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use diagnose::Diagnosis;
+
+#[cfg(feature = "wrappers")]
+mod dyn_queue;
+#[cfg(feature = "wrappers")]
+pub use dyn_queue::{EventSink, EventWaitable};
+
+#[cfg(feature = "wrappers")]
+mod log;
+#[cfg(feature = "wrappers")]
+pub use log::EventLine;
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod mock;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use mock::MockQueue;
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod backend;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use backend::{Backend, DynQueue};
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod pending_ctl;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use pending_ctl::PendingCtl;
+
+#[cfg(feature = "wrappers")]
+mod signal;
+#[cfg(feature = "wrappers")]
+pub use signal::{Signal, SignalScope, SignalWait};
+
+#[cfg(feature = "wrappers")]
+mod static_loop;
+#[cfg(feature = "wrappers")]
+pub use static_loop::StaticLoop;
+
+#[cfg(feature = "wrappers")]
+mod wait;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use wait::{poll_many, ReadinessBitmap};
+#[cfg(feature = "wrappers")]
+pub use wait::{wait_readable, wait_writable};
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod stdio;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use stdio::{StdRegistration, StdStream};
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod event_loop;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use event_loop::{EventLoop, Handle, Priority};
+#[cfg(all(feature = "wrappers", feature = "std"))]
+pub use event_loop::{Metrics, SourceMetrics, Stall};
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod state_machine;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use state_machine::{Machines, StateMachine, StateMachineLoop};
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod scope;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use scope::EventScope;
+
+#[cfg(feature = "std")]
+mod counter;
+#[cfg(feature = "std")]
+pub use counter::EventCounter;
+
+#[cfg(feature = "std")]
+mod waker;
+#[cfg(feature = "std")]
+pub use waker::EventWaker;
+
+#[cfg(all(feature = "wrappers", feature = "std"))]
+mod interrupt;
+#[cfg(all(feature = "wrappers", feature = "std"))]
+pub use interrupt::InterruptHandle;
+
+#[cfg(all(feature = "wrappers", feature = "std"))]
+mod global;
+#[cfg(all(feature = "wrappers", feature = "std"))]
+pub use global::global;
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+mod scoped;
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub use scoped::{Scoped, ScopedQueue};
+
+#[cfg(all(feature = "wrappers", feature = "std"))]
+mod history;
+#[cfg(all(feature = "wrappers", feature = "std"))]
+pub use history::HistoryEntry;
+
+#[cfg(all(feature = "wrappers", feature = "std"))]
+mod rate_limit;
+#[cfg(all(feature = "wrappers", feature = "std"))]
+pub use rate_limit::RateLimited;
+
+#[cfg(all(feature = "wrappers", feature = "std"))]
+mod reactor;
+#[cfg(all(feature = "wrappers", feature = "std"))]
+pub use reactor::{ready, Reactor, ReactorHandle, Ready, WeakReactorHandle};
+
+#[cfg(all(feature = "wrappers", feature = "std"))]
+mod retry;
+#[cfg(all(feature = "wrappers", feature = "std"))]
+pub use retry::{RetryError, RetryPolicy};
+
+#[cfg(feature = "std")]
+mod drain;
+#[cfg(feature = "std")]
+pub use drain::{drain_readable, Drained};
+
+#[cfg(all(feature = "wrappers", feature = "std"))]
+mod writer;
+#[cfg(all(feature = "wrappers", feature = "std"))]
+pub use writer::EventBufWriter;
+
+#[cfg(all(feature = "wrappers", feature = "std"))]
+pub mod child;
+
+#[cfg(feature = "bench")]
+pub mod bench;
+
+#[cfg(feature = "wrappers")]
+pub mod intrusive;
+
+#[cfg(feature = "wrappers")]
+pub mod typed;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub mod mux;
+
+#[cfg(all(feature = "wrappers", feature = "alloc"))]
+pub mod ops;
+
+#[cfg(feature = "polling")]
+pub mod polling;
@@ -0,0 +1,101 @@
+//! Formatting events without allocating, for drivers logging to a debug scheme.
+use core::fmt;
+
+/// A line of text formatted into a fixed buffer on the stack
+///
+/// Text that does not fit is dropped, along with all text written after it, ending the line at
+/// the last whole character that fits.
+pub struct EventLine<const N: usize = 128> {
+    buf: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> EventLine<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        // SAFETY: Only whole UTF-8 characters are ever copied into the buffer.
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+    /// Whether some of the text did not fit
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+impl<const N: usize> Default for EventLine<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<const N: usize> fmt::Write for EventLine<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Ok(());
+        }
+        let mut count = s.len().min(N - self.len);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+        self.buf[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        self.truncated |= count < s.len();
+        // Not an error, so that the rest of the formatting is skipped rather than failing.
+        Ok(())
+    }
+}
+impl<const N: usize> fmt::Display for EventLine<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl<const N: usize> fmt::Debug for EventLine<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Format an [`Event`](crate::Event) into an [`EventLine`], with the source location, the flag
+/// names and the `Debug` of its user data
+///
+/// ```ignore
+/// let event = queue.next_event()?;
+/// debug_scheme.write_all(event::log_event!(event).as_bytes())?;
+/// // src/main.rs:42: fd 5 read|write Socket(3)
+/// ```
+///
+/// The line is 128 bytes long by default, and another length can be given as
+/// `log_event!(event, 256)`.
+#[macro_export]
+macro_rules! log_event {
+    ($event:expr) => {
+        $crate::log_event!($event, 128)
+    };
+    ($event:expr, $len:expr) => {{
+        let event = &$event;
+        let mut line = $crate::EventLine::<$len>::new();
+        let _ = ::core::fmt::Write::write_fmt(
+            &mut line,
+            ::core::format_args!(
+                "{}:{}: fd {} {} {:?}",
+                ::core::file!(),
+                ::core::line!(),
+                event.fd,
+                event.flags,
+                event.user_data
+            ),
+        );
+        line
+    }};
+}
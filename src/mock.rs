@@ -0,0 +1,90 @@
+//! An in-memory queue for testing code that is generic over [`EventQueueApi`].
+//!
+//! ```ignore
+//! let queue = MockQueue::new();
+//! server.register(&queue)?;
+//! queue.post(listener_fd, EventFlags::READ);
+//! server.poll(&queue)?;
+//! ```
+use alloc::collections::{BTreeMap, VecDeque};
+use core::cell::RefCell;
+
+use libredox::errno::{EAGAIN, EINVAL};
+use libredox::error::{Error, Result};
+
+use crate::{Event, EventFlags, EventQueueApi, UserData, MAX_APP_VALUE};
+
+/// A queue whose events are posted by the test rather than the kernel
+///
+/// It checks subscriptions like an [`EventQueue`](crate::EventQueue) would, and delivers events
+/// in the order they were posted. Since nothing can post events while it waits, waiting with no
+/// event pending fails with `EAGAIN` instead of blocking.
+pub struct MockQueue<U: UserData> {
+    subscriptions: RefCell<BTreeMap<usize, (U, EventFlags)>>,
+    events: RefCell<VecDeque<Event<U>>>,
+}
+
+impl<U: UserData> MockQueue<U> {
+    pub fn new() -> Self {
+        Self {
+            subscriptions: RefCell::new(BTreeMap::new()),
+            events: RefCell::new(VecDeque::new()),
+        }
+    }
+    /// Report `fd` as ready for `flags`, returning whether an event was queued
+    ///
+    /// As with the kernel, the event only has the flags `fd` is subscribed to, and none is queued
+    /// if it is not subscribed to any of them.
+    pub fn post(&self, fd: usize, flags: EventFlags) -> bool {
+        let Some(&(user_data, interest)) = self.subscriptions.borrow().get(&fd) else {
+            return false;
+        };
+        let flags = flags & interest;
+        if flags.is_empty() {
+            return false;
+        }
+        self.events.borrow_mut().push_back(Event {
+            user_data,
+            flags,
+            fd,
+        });
+        true
+    }
+    /// The user data and flags `fd` is subscribed with, if it is subscribed
+    pub fn subscription(&self, fd: usize) -> Option<(U, EventFlags)> {
+        self.subscriptions.borrow().get(&fd).copied()
+    }
+    /// The number of events posted but not yet returned
+    pub fn pending(&self) -> usize {
+        self.events.borrow().len()
+    }
+}
+impl<U: UserData> Default for MockQueue<U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<U: UserData> EventQueueApi<U> for MockQueue<U> {
+    fn subscribe(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        if flags.is_empty() {
+            return self.unsubscribe(fd);
+        }
+        if data.into_user_data() > MAX_APP_VALUE {
+            return Err(Error::new(EINVAL));
+        }
+        self.subscriptions.borrow_mut().insert(fd, (data, flags));
+        Ok(())
+    }
+    /// Unsubscribe from `fd`, also dropping the events posted for it
+    fn unsubscribe(&self, fd: usize) -> Result<()> {
+        self.subscriptions.borrow_mut().remove(&fd);
+        self.events.borrow_mut().retain(|event| event.fd != fd);
+        Ok(())
+    }
+    fn wait(&self) -> Result<Event<U>> {
+        self.events
+            .borrow_mut()
+            .pop_front()
+            .ok_or(Error::new(EAGAIN))
+    }
+}
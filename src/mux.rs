@@ -0,0 +1,149 @@
+//! Per-client bookkeeping for scheme servers sharing one event queue between clients.
+//!
+//! ```ignore
+//! let mut mux = ClientMux::new()?;
+//! let client = mux.add_client(Session::new(socket));
+//! mux.subscribe(client, socket_fd, EventFlags::READ)?;
+//!
+//! let event = mux.next_event()?;
+//! if event.context.handle(event.fd, event.flags).is_disconnect() {
+//!     let id = event.client;
+//!     mux.remove_client(id);
+//! }
+//! ```
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use libredox::errno::{EEXIST, ENOENT};
+use libredox::error::{Error, Result};
+
+use crate::{EventFlags, RawEventQueue};
+
+/// Identifies a client of a [`ClientMux`]
+///
+/// Ids are never reused, so a stale id cannot refer to a later client.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ClientId(u64);
+
+struct Client<C> {
+    context: C,
+    fds: Vec<usize>,
+}
+
+/// An event routed to the client owning its fd
+pub struct ClientEvent<'a, C> {
+    pub client: ClientId,
+    pub context: &'a mut C,
+    pub fd: usize,
+    pub flags: EventFlags,
+}
+
+/// An event queue whose subscriptions each belong to a client with context `C`
+///
+/// Every fd belongs to at most one client. Removing a client unsubscribes all of its fds, and
+/// events for them that were already queued are skipped.
+pub struct ClientMux<C> {
+    queue: RawEventQueue,
+    clients: BTreeMap<ClientId, Client<C>>,
+    owners: BTreeMap<usize, ClientId>,
+    next_id: u64,
+}
+
+impl<C> ClientMux<C> {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            queue: RawEventQueue::new()?,
+            clients: BTreeMap::new(),
+            owners: BTreeMap::new(),
+            next_id: 0,
+        })
+    }
+    /// Register a client, which starts without any subscriptions
+    pub fn add_client(&mut self, context: C) -> ClientId {
+        let id = ClientId(self.next_id);
+        self.next_id += 1;
+        self.clients.insert(
+            id,
+            Client {
+                context,
+                fds: Vec::new(),
+            },
+        );
+        id
+    }
+    /// Unsubscribe every fd of a client, and return its context
+    ///
+    /// All fds are unsubscribed even if some fail, for example because they were already closed.
+    pub fn remove_client(&mut self, id: ClientId) -> Option<C> {
+        let client = self.clients.remove(&id)?;
+        for fd in client.fds {
+            self.owners.remove(&fd);
+            let _ = self.queue.unsubscribe(fd);
+        }
+        Some(client.context)
+    }
+    /// Subscribe to events of `fd` on behalf of a client, or change the flags of its subscription
+    ///
+    /// Fails with `ENOENT` if the client does not exist, and with `EEXIST` if `fd` belongs to
+    /// another client.
+    pub fn subscribe(&mut self, id: ClientId, fd: usize, flags: EventFlags) -> Result<()> {
+        let client = self.clients.get_mut(&id).ok_or(Error::new(ENOENT))?;
+        match self.owners.get(&fd) {
+            Some(owner) if *owner != id => return Err(Error::new(EEXIST)),
+            _ => (),
+        }
+        self.queue.subscribe(fd, fd, flags)?;
+        if self.owners.insert(fd, id).is_none() {
+            client.fds.push(fd);
+        }
+        Ok(())
+    }
+    /// Unsubscribe from `fd` on behalf of a client
+    ///
+    /// Fails with `ENOENT` if `fd` does not belong to the client.
+    pub fn unsubscribe(&mut self, id: ClientId, fd: usize) -> Result<()> {
+        if self.owners.get(&fd) != Some(&id) {
+            return Err(Error::new(ENOENT));
+        }
+        self.queue.unsubscribe(fd)?;
+        self.owners.remove(&fd);
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.fds.retain(|other| *other != fd);
+        }
+        Ok(())
+    }
+    pub fn client(&self, id: ClientId) -> Option<&C> {
+        self.clients.get(&id).map(|client| &client.context)
+    }
+    pub fn client_mut(&mut self, id: ClientId) -> Option<&mut C> {
+        self.clients.get_mut(&id).map(|client| &mut client.context)
+    }
+    /// Iterate over the clients and their contexts
+    pub fn clients(&self) -> impl Iterator<Item = (ClientId, &C)> + '_ {
+        self.clients
+            .iter()
+            .map(|(id, client)| (*id, &client.context))
+    }
+    /// Block until the next event, and return it along with the client it belongs to
+    pub fn next_event(&mut self) -> Result<ClientEvent<'_, C>> {
+        loop {
+            let raw = self.queue.next_event()?;
+            if let Some(id) = self.owners.get(&raw.fd).copied() {
+                let client = self
+                    .clients
+                    .get_mut(&id)
+                    .expect("owners only refer to existing clients");
+                return Ok(ClientEvent {
+                    client: id,
+                    context: &mut client.context,
+                    fd: raw.fd,
+                    flags: EventFlags::from_bits_retain(raw.flags),
+                });
+            }
+        }
+    }
+    #[inline]
+    pub fn as_raw_fd(&self) -> usize {
+        self.queue.as_raw_fd()
+    }
+}
@@ -0,0 +1,206 @@
+//! Experimental completion-based I/O: submit reads and writes, and be told when they are done.
+//!
+//! This is currently layered on readiness events: a submitted operation is attempted right away,
+//! and again each time its fd becomes ready, until it no longer fails with `EAGAIN`. The API only
+//! deals in owned buffers and completions, so it can later be backed by a kernel submission
+//! interface without changes to callers.
+//!
+//! ```ignore
+//! let mut ring = Ring::new()?;
+//! let id = ring.submit_read(socket_fd, vec![0; 4096])?;
+//! let done = ring.complete()?;
+//! assert_eq!(done.id, id);
+//! let len = done.result?;
+//! ```
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+
+use libredox::errno::ENOENT;
+use libredox::error::{Error, Result};
+
+use crate::{EventFlags, RawEventQueue};
+
+/// Identifies a submitted operation in its [`Completion`]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct OpId(u64);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    Read,
+    Write,
+}
+
+struct Op {
+    id: OpId,
+    kind: Kind,
+    buf: Vec<u8>,
+}
+
+/// The outcome of an operation
+#[derive(Debug)]
+pub struct Completion {
+    pub id: OpId,
+    pub fd: usize,
+    /// The number of bytes read or written
+    pub result: Result<usize>,
+    /// The buffer that was submitted, returned to the caller
+    pub buf: Vec<u8>,
+}
+
+/// A queue of read and write operations on nonblocking fds, and their completions
+///
+/// Operations on the same fd and of the same kind complete in the order they were submitted.
+/// Fds must be nonblocking, otherwise attempting an operation blocks the caller.
+pub struct Ring {
+    queue: RawEventQueue,
+    pending: BTreeMap<usize, VecDeque<Op>>,
+    subscribed: BTreeMap<usize, EventFlags>,
+    completed: VecDeque<Completion>,
+    next_id: u64,
+}
+
+impl Ring {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            queue: RawEventQueue::new()?,
+            pending: BTreeMap::new(),
+            subscribed: BTreeMap::new(),
+            completed: VecDeque::new(),
+            next_id: 0,
+        })
+    }
+    /// Read up to `buf.len()` bytes from `fd` into `buf`
+    pub fn submit_read(&mut self, fd: usize, buf: Vec<u8>) -> Result<OpId> {
+        self.submit(fd, Kind::Read, buf)
+    }
+    /// Write `buf` to `fd`, completing with the number of bytes written, which may be fewer
+    pub fn submit_write(&mut self, fd: usize, buf: Vec<u8>) -> Result<OpId> {
+        self.submit(fd, Kind::Write, buf)
+    }
+    fn submit(&mut self, fd: usize, kind: Kind, buf: Vec<u8>) -> Result<OpId> {
+        let id = OpId(self.next_id);
+        self.next_id += 1;
+
+        self.pending
+            .entry(fd)
+            .or_default()
+            .push_back(Op { id, kind, buf });
+        if let Err(err) = self.update_interest(fd) {
+            self.cancel(id);
+            return Err(err);
+        }
+        self.progress(fd, kind);
+        Ok(id)
+    }
+    /// Cancel an operation that has not completed yet, returning its buffer
+    pub fn cancel(&mut self, id: OpId) -> Option<Vec<u8>> {
+        let (fd, index) = self.pending.iter().find_map(|(fd, ops)| {
+            let index = ops.iter().position(|op| op.id == id)?;
+            Some((*fd, index))
+        })?;
+        let op = self.pending.get_mut(&fd)?.remove(index)?;
+        let _ = self.update_interest(fd);
+        Some(op.buf)
+    }
+    /// The number of operations that have been submitted but not completed
+    pub fn in_flight(&self) -> usize {
+        self.pending.values().map(VecDeque::len).sum()
+    }
+    /// Block until an operation completes, and return its completion
+    ///
+    /// Fails with `ENOENT` if no operation is in flight, as this would block forever.
+    pub fn complete(&mut self) -> Result<Completion> {
+        loop {
+            if let Some(completion) = self.completed.pop_front() {
+                return Ok(completion);
+            }
+            if self.pending.is_empty() {
+                return Err(Error::new(ENOENT));
+            }
+            let event = self.queue.next_event()?;
+            let flags = EventFlags::from_bits_retain(event.flags);
+            if flags.contains(EventFlags::READ) {
+                self.progress(event.fd, Kind::Read);
+            }
+            if flags.contains(EventFlags::WRITE) {
+                self.progress(event.fd, Kind::Write);
+            }
+        }
+    }
+    /// Return a completion if one is available, without blocking
+    pub fn try_complete(&mut self) -> Result<Option<Completion>> {
+        while self.completed.is_empty() {
+            let Some(event) = self.queue.try_next_event()? else {
+                break;
+            };
+            let flags = EventFlags::from_bits_retain(event.flags);
+            if flags.contains(EventFlags::READ) {
+                self.progress(event.fd, Kind::Read);
+            }
+            if flags.contains(EventFlags::WRITE) {
+                self.progress(event.fd, Kind::Write);
+            }
+        }
+        Ok(self.completed.pop_front())
+    }
+    #[inline]
+    pub fn as_raw_fd(&self) -> usize {
+        self.queue.as_raw_fd()
+    }
+
+    // Attempt the operations of `kind` on `fd` in order, until one would block.
+    fn progress(&mut self, fd: usize, kind: Kind) {
+        let Some(ops) = self.pending.get_mut(&fd) else {
+            return;
+        };
+        while let Some(index) = ops.iter().position(|op| op.kind == kind) {
+            let op = &mut ops[index];
+            let result = loop {
+                let result = match kind {
+                    Kind::Read => libredox::call::read(fd, &mut op.buf),
+                    Kind::Write => libredox::call::write(fd, &op.buf),
+                };
+                match result {
+                    Err(err) if err.is_interrupt() => continue,
+                    result => break result,
+                }
+            };
+            if result.is_err_and(Error::is_wouldblock) {
+                break;
+            }
+            let op = ops.remove(index).expect("index was just found");
+            self.completed.push_back(Completion {
+                id: op.id,
+                fd,
+                result,
+                buf: op.buf,
+            });
+        }
+        let _ = self.update_interest(fd);
+    }
+    // Subscribe `fd` to the readiness its pending operations need.
+    fn update_interest(&mut self, fd: usize) -> Result<()> {
+        let flags = self.pending.get(&fd).map_or(EventFlags::empty(), |ops| {
+            ops.iter().fold(EventFlags::empty(), |flags, op| {
+                flags
+                    | match op.kind {
+                        Kind::Read => EventFlags::READ,
+                        Kind::Write => EventFlags::WRITE,
+                    }
+            })
+        });
+        if flags.is_empty() {
+            self.pending.remove(&fd);
+        }
+        if self.subscribed.get(&fd).copied().unwrap_or_default() == flags {
+            return Ok(());
+        }
+        if flags.is_empty() {
+            self.subscribed.remove(&fd);
+            return self.queue.unsubscribe(fd);
+        }
+        self.queue.subscribe(fd, fd, flags)?;
+        self.subscribed.insert(fd, flags);
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! Subscription changes requested by other threads, applied by the thread waiting on a queue.
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use libredox::error::Result;
+
+use crate::{EventFlags, EventQueue, UserData};
+
+struct Request {
+    fd: usize,
+    user_data: usize,
+    flags: EventFlags,
+    // The request made before this one.
+    next: *mut Request,
+}
+
+/// A lock-free buffer of subscription changes, applied to an [`EventQueue`] between waits
+///
+/// Any thread can request changes, which the thread owning the queue applies in the order they
+/// were made, when it next reads an event (see [`EventQueue::set_pending_ctl`]). Subscriptions are
+/// thus never changed while the queue is being waited on. Requests made while the owner is
+/// blocked are only applied once it wakes up and reads the following event, so requesters should
+/// wake it, e.g. through an [`EventWaker`](crate::EventWaker).
+pub struct PendingCtl<U: UserData> {
+    // The most recent request, linked to the earlier ones.
+    head: AtomicPtr<Request>,
+    _marker: PhantomData<fn(U)>,
+}
+
+impl<U: UserData> PendingCtl<U> {
+    pub const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+    /// Request subscribing to events produced by `fd`
+    pub fn subscribe(&self, fd: usize, data: U, flags: EventFlags) {
+        self.push(fd, data.into_user_data(), flags);
+    }
+    /// Request unsubscribing from events produced by `fd`
+    pub fn unsubscribe(&self, fd: usize) {
+        self.push(fd, 0, EventFlags::empty());
+    }
+    /// Whether no requests are waiting to be applied
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+    /// Apply every request made so far to `queue`, in order, returning how many were applied
+    ///
+    /// A failed request does not stop the following ones from being applied, and the first
+    /// failure is returned once all have been.
+    pub fn apply(&self, queue: &EventQueue<U>) -> Result<usize> {
+        let mut request = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+
+        // Reverse the list, which is newest first.
+        let mut oldest = ptr::null_mut();
+        while !request.is_null() {
+            // SAFETY: Requests taken off the list are owned by this call.
+            let next = unsafe { (*request).next };
+            unsafe { (*request).next = oldest };
+            oldest = request;
+            request = next;
+        }
+
+        let mut res = Ok(0);
+        while !oldest.is_null() {
+            // SAFETY: The request was allocated by `push` and is no longer shared.
+            let request = unsafe { Box::from_raw(oldest) };
+            oldest = request.next;
+            let applied = queue.subscribe_raw(request.fd, request.user_data, request.flags);
+            match (&mut res, applied) {
+                (Ok(count), Ok(())) => *count += 1,
+                (Ok(_), Err(err)) => res = Err(err),
+                (Err(_), _) => {}
+            }
+        }
+        res
+    }
+    fn push(&self, fd: usize, user_data: usize, flags: EventFlags) {
+        let request = Box::into_raw(Box::new(Request {
+            fd,
+            user_data,
+            flags,
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: The request is not shared until the exchange succeeds.
+            unsafe { (*request).next = head };
+            match self.head.compare_exchange_weak(
+                head,
+                request,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+impl<U: UserData> Default for PendingCtl<U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<U: UserData> Drop for PendingCtl<U> {
+    fn drop(&mut self) {
+        let mut request = *self.head.get_mut();
+        while !request.is_null() {
+            // SAFETY: The list is exclusively owned once the buffer is dropped.
+            let owned = unsafe { Box::from_raw(request) };
+            request = owned.next;
+        }
+    }
+}
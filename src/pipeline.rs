@@ -0,0 +1,129 @@
+//! Layering event transformations over any [`EventQueueApi`].
+//!
+//! ```ignore
+//! let queue = EventQueue::<Token>::new()?
+//!     .inspect_events(|event| log::trace!("{:?} on fd {}", event.flags, event.fd))
+//!     .filter_events(|event| event.flags.contains(EventFlags::READ));
+//! let event = queue.wait()?;
+//! ```
+use core::marker::PhantomData;
+
+use libredox::error::Result;
+
+use crate::{Event, EventFlags, EventQueueApi, UserData};
+
+/// Combinators for [`EventQueueApi`] implementations, each returning a queue with the same
+/// interface
+pub trait EventQueueExt<U: UserData>: EventQueueApi<U> + Sized {
+    /// Pass every event through `f` before returning it
+    fn map_events<F: Fn(Event<U>) -> Event<U>>(self, f: F) -> MapEvents<Self, F, U> {
+        MapEvents {
+            inner: self,
+            f,
+            _marker: PhantomData,
+        }
+    }
+    /// Drop the events for which `f` returns false
+    fn filter_events<F: Fn(&Event<U>) -> bool>(self, f: F) -> FilterEvents<Self, F, U> {
+        FilterEvents {
+            inner: self,
+            f,
+            _marker: PhantomData,
+        }
+    }
+    /// Call `f` with every event before returning it, e.g. for logging or metrics
+    fn inspect_events<F: Fn(&Event<U>)>(self, f: F) -> InspectEvents<Self, F, U> {
+        InspectEvents {
+            inner: self,
+            f,
+            _marker: PhantomData,
+        }
+    }
+}
+impl<U: UserData, Q: EventQueueApi<U>> EventQueueExt<U> for Q {}
+
+macro_rules! combinator {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        pub struct $name<Q, F, U> {
+            inner: Q,
+            f: F,
+            _marker: PhantomData<fn() -> U>,
+        }
+        impl<Q, F, U> $name<Q, F, U> {
+            #[inline]
+            pub fn get_ref(&self) -> &Q {
+                &self.inner
+            }
+            #[inline]
+            pub fn into_inner(self) -> Q {
+                self.inner
+            }
+        }
+    };
+}
+combinator!(
+    /// A queue returning events transformed by a function, see [`EventQueueExt::map_events`]
+    MapEvents
+);
+combinator!(
+    /// A queue dropping some events, see [`EventQueueExt::filter_events`]
+    FilterEvents
+);
+combinator!(
+    /// A queue calling a function on every event, see [`EventQueueExt::inspect_events`]
+    InspectEvents
+);
+
+impl<U: UserData, Q: EventQueueApi<U>, F: Fn(Event<U>) -> Event<U>> EventQueueApi<U>
+    for MapEvents<Q, F, U>
+{
+    #[inline]
+    fn subscribe(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        self.inner.subscribe(fd, data, flags)
+    }
+    #[inline]
+    fn unsubscribe(&self, fd: usize) -> Result<()> {
+        self.inner.unsubscribe(fd)
+    }
+    fn wait(&self) -> Result<Event<U>> {
+        self.inner.wait().map(&self.f)
+    }
+}
+impl<U: UserData, Q: EventQueueApi<U>, F: Fn(&Event<U>) -> bool> EventQueueApi<U>
+    for FilterEvents<Q, F, U>
+{
+    #[inline]
+    fn subscribe(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        self.inner.subscribe(fd, data, flags)
+    }
+    #[inline]
+    fn unsubscribe(&self, fd: usize) -> Result<()> {
+        self.inner.unsubscribe(fd)
+    }
+    fn wait(&self) -> Result<Event<U>> {
+        loop {
+            let event = self.inner.wait()?;
+            if (self.f)(&event) {
+                return Ok(event);
+            }
+        }
+    }
+}
+impl<U: UserData, Q: EventQueueApi<U>, F: Fn(&Event<U>)> EventQueueApi<U>
+    for InspectEvents<Q, F, U>
+{
+    #[inline]
+    fn subscribe(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        self.inner.subscribe(fd, data, flags)
+    }
+    #[inline]
+    fn unsubscribe(&self, fd: usize) -> Result<()> {
+        self.inner.unsubscribe(fd)
+    }
+    fn wait(&self) -> Result<Event<U>> {
+        let event = self.inner.wait()?;
+        (self.f)(&event);
+        Ok(event)
+    }
+}
@@ -0,0 +1,228 @@
+//! An adapter with the API shape of the [`polling`](https://docs.rs/polling) crate.
+//!
+//! This allows crates built on `polling` (such as `async-io`) to run on Redox by swapping in
+//! [`Poller`] as their backend. As with `polling`, interests are oneshot: once an event has been
+//! delivered for a source, it must be rearmed with [`Poller::modify`] before it is reported
+//! again.
+//!
+//! Unlike `polling`, sources are plain file descriptors, and [`Poller::notify`] needs the `std`
+//! feature, as it is built on an [`EventWaker`](crate::EventWaker).
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::ops::DerefMut;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(feature = "std")]
+use libredox::errno::EIO;
+use libredox::errno::{EEXIST, ENOENT};
+use libredox::error::{Error, Result};
+
+#[cfg(feature = "std")]
+use crate::wrappers::reserved;
+#[cfg(feature = "std")]
+use crate::EventWaker;
+use crate::{EventFlags, RawEvent, RawEventQueue, Timeout, WaitFlags};
+
+/// Interest in, or readiness of, a source
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Event {
+    pub key: usize,
+    pub readable: bool,
+    pub writable: bool,
+}
+
+impl Event {
+    /// Interest in both read and write events
+    pub const fn all(key: usize) -> Self {
+        Self {
+            key,
+            readable: true,
+            writable: true,
+        }
+    }
+    /// Interest in read events
+    pub const fn readable(key: usize) -> Self {
+        Self {
+            key,
+            readable: true,
+            writable: false,
+        }
+    }
+    /// Interest in write events
+    pub const fn writable(key: usize) -> Self {
+        Self {
+            key,
+            readable: false,
+            writable: true,
+        }
+    }
+    /// No interest in any events
+    pub const fn none(key: usize) -> Self {
+        Self {
+            key,
+            readable: false,
+            writable: false,
+        }
+    }
+    fn flags(self) -> EventFlags {
+        let mut flags = EventFlags::empty();
+        flags.set(EventFlags::READ, self.readable);
+        flags.set(EventFlags::WRITE, self.writable);
+        flags
+    }
+}
+
+/// A list of events filled by [`Poller::wait`]
+#[derive(Debug, Default)]
+pub struct Events {
+    inner: Vec<RawEvent>,
+}
+
+impl Events {
+    /// The default number of events read by a single [`Poller::wait`]
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: Vec::with_capacity(capacity),
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = Event> + '_ {
+        self.inner.iter().map(|raw| {
+            let flags = EventFlags::from_bits_retain(raw.flags);
+            Event {
+                key: raw.user_data,
+                readable: flags.contains(EventFlags::READ),
+                writable: flags.contains(EventFlags::WRITE),
+            }
+        })
+    }
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+}
+
+// The fds added and not deleted since, whether armed or not. Without `std` there is no
+// `notify`, and so no reason to share the poller between threads.
+#[cfg(feature = "std")]
+type Sources = Mutex<BTreeSet<usize>>;
+#[cfg(not(feature = "std"))]
+type Sources = core::cell::RefCell<BTreeSet<usize>>;
+
+/// A `polling`-style wrapper around an event queue
+pub struct Poller {
+    queue: RawEventQueue,
+    sources: Sources,
+    #[cfg(feature = "std")]
+    waker: EventWaker,
+}
+
+impl Poller {
+    pub fn new() -> Result<Self> {
+        let queue = RawEventQueue::new()?;
+        #[cfg(feature = "std")]
+        let waker =
+            EventWaker::new().map_err(|err| Error::new(err.raw_os_error().unwrap_or(EIO)))?;
+        #[cfg(feature = "std")]
+        queue.subscribe(waker.as_raw_fd(), reserved::WAKER, EventFlags::READ)?;
+
+        Ok(Self {
+            queue,
+            sources: Sources::new(BTreeSet::new()),
+            #[cfg(feature = "std")]
+            waker,
+        })
+    }
+    fn sources(&self) -> impl DerefMut<Target = BTreeSet<usize>> + '_ {
+        #[cfg(feature = "std")]
+        return self.sources.lock().unwrap_or_else(PoisonError::into_inner);
+        #[cfg(not(feature = "std"))]
+        return self.sources.borrow_mut();
+    }
+    /// Start watching `fd`, using `interest.key` as the key of its events
+    ///
+    /// Fails with `EEXIST` if `fd` is already watched, even if it has been disarmed by an event.
+    pub fn add(&self, fd: usize, interest: Event) -> Result<()> {
+        let mut sources = self.sources();
+        if sources.contains(&fd) {
+            return Err(Error::new(EEXIST));
+        }
+        self.queue.subscribe(fd, interest.key, interest.flags())?;
+        sources.insert(fd);
+        Ok(())
+    }
+    /// Change the key or interest of `fd`, rearming it after an event
+    pub fn modify(&self, fd: usize, interest: Event) -> Result<()> {
+        self.queue.subscribe(fd, interest.key, interest.flags())
+    }
+    /// Stop watching `fd`
+    ///
+    /// This also succeeds if `fd` was disarmed by an event and not rearmed since.
+    pub fn delete(&self, fd: usize) -> Result<()> {
+        self.sources().remove(&fd);
+        match self.queue.unsubscribe(fd) {
+            Err(err) if err.errno() == ENOENT => Ok(()),
+            res => res,
+        }
+    }
+    /// Make the current or next call to [`wait`](Self::wait) return, from any thread
+    ///
+    /// Several notifications before the wait returns are coalesced into one.
+    #[cfg(feature = "std")]
+    pub fn notify(&self) -> Result<()> {
+        self.waker
+            .wake()
+            .map_err(|err| Error::new(err.raw_os_error().unwrap_or(EIO)))
+    }
+    /// Wait for events, appending them to `events`
+    ///
+    /// Up to the spare capacity of `events` is read at once. Returns the number of events
+    /// appended, which is zero if `timeout` expired first or the poller was notified.
+    pub fn wait(&self, events: &mut Events, timeout: Option<Duration>) -> Result<usize> {
+        let max = (events.inner.capacity() - events.inner.len()).max(1);
+        events.inner.reserve(max);
+
+        let read = self.queue.wait(
+            &mut events.inner.spare_capacity_mut()[..max],
+            timeout.map(Timeout::from),
+            WaitFlags::NONE,
+        )?;
+        // Disarm the sources that fired, to provide oneshot semantics. The events are reported
+        // even if that fails, as they have been consumed; a source left armed only fires again.
+        for raw in read.iter() {
+            #[cfg(feature = "std")]
+            if raw.fd == self.waker.as_raw_fd() {
+                continue;
+            }
+            let _ = self.queue.unsubscribe(raw.fd);
+        }
+        let count = read.len();
+        // SAFETY: The kernel has initialized the first `count` events of the spare capacity.
+        unsafe { events.inner.set_len(events.inner.len() + count) };
+        let start = events.inner.len() - count;
+
+        #[cfg(feature = "std")]
+        {
+            let waker = self.waker.as_raw_fd();
+            if events.inner[start..].iter().any(|raw| raw.fd == waker) {
+                events.inner.retain(|raw| raw.fd != waker);
+                let _ = self.waker.wake_count();
+            }
+        }
+        Ok(events.inner.len() - start)
+    }
+}
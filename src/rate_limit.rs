@@ -0,0 +1,103 @@
+//! Limiting how often a state machine is driven, to survive event storms.
+use core::time::Duration;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::{Event, StateMachine, UserData};
+
+/// A [`StateMachine`] wrapper passing at most `max_per_second` events a second to the inner one
+///
+/// Excess events are kept pending, one per fd, with the flags of every event for that fd merged
+/// and the user data of the latest one. They are delivered oldest first by
+/// [`flush`](Self::flush) once the rate allows it, and before any later event.
+///
+/// Nothing calls `flush` on its own: the wrapper only runs when an event arrives, and
+/// [`StateMachineLoop`](crate::StateMachineLoop) blocks until the next one. While an event is
+/// pending, the caller must wait at most [`retry_after`](Self::retry_after), e.g. with
+/// [`EventQueue::next_event_timeout`](crate::EventQueue::next_event_timeout), and then flush.
+pub struct RateLimited<U: UserData, S> {
+    inner: S,
+    max_per_second: u32,
+    window_start: Instant,
+    delivered: u32,
+    pending: VecDeque<Event<U>>,
+}
+
+impl<U: UserData, S: StateMachine<U>> RateLimited<U, S> {
+    pub fn new(inner: S, max_per_second: u32) -> Self {
+        Self {
+            inner,
+            max_per_second: max_per_second.max(1),
+            window_start: Instant::now(),
+            delivered: 0,
+            pending: VecDeque::new(),
+        }
+    }
+    /// Deliver as many pending events as the rate allows, returning how many were delivered
+    pub fn flush(&mut self) -> usize {
+        let mut count = 0;
+        while !self.pending.is_empty() && self.take_budget() {
+            if let Some(event) = self.pending.pop_front() {
+                self.inner.on_event(event);
+                count += 1;
+            }
+        }
+        count
+    }
+    /// Whether excess events are waiting to be delivered
+    #[inline]
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+    /// How long until a pending event can be delivered, if there is one
+    pub fn retry_after(&self) -> Option<Duration> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        if self.delivered < self.max_per_second {
+            return Some(Duration::ZERO);
+        }
+        Some(Duration::from_secs(1).saturating_sub(self.window_start.elapsed()))
+    }
+    #[inline]
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+    // Count an event against the current window, returning false if it is used up.
+    fn take_budget(&mut self) -> bool {
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.delivered = 0;
+        }
+        if self.delivered >= self.max_per_second {
+            return false;
+        }
+        self.delivered += 1;
+        true
+    }
+}
+impl<U: UserData, S: StateMachine<U>> StateMachine<U> for RateLimited<U, S> {
+    fn on_event(&mut self, event: Event<U>) {
+        // Pending events go first, and a deferred event joins the one pending for its fd.
+        self.flush();
+        if self.pending.is_empty() && self.take_budget() {
+            self.inner.on_event(event);
+            return;
+        }
+        match self
+            .pending
+            .iter_mut()
+            .find(|pending| pending.fd == event.fd)
+        {
+            Some(pending) => {
+                pending.flags |= event.flags;
+                pending.user_data = event.user_data;
+            }
+            None => self.pending.push_back(event),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct RawEventV1 {
     // NOTE: This field will likely be removed soon (v2), as user_data can already uniquely
@@ -46,8 +47,72 @@ extern "C" {
 }
 bitflags::bitflags! {
     #[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct EventFlags: u32 {
         const READ = 1;
         const WRITE = 2;
     }
 }
+
+/// Flags are written in lowercase, separated by `|`, e.g. `read|write`, with `none` for no flags
+/// and unknown bits written in hexadecimal.
+impl core::fmt::Display for EventFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use core::fmt::Write;
+
+        if self.is_empty() {
+            return f.write_str("none");
+        }
+        let mut separator = "";
+        for (name, _) in self.iter_names() {
+            f.write_str(separator)?;
+            for c in name.chars() {
+                f.write_char(c.to_ascii_lowercase())?;
+            }
+            separator = "|";
+        }
+        let unknown = self.bits() & !Self::all().bits();
+        if unknown != 0 {
+            write!(f, "{separator}{unknown:#x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The error returned when parsing [`EventFlags`] fails
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseEventFlagsError(());
+
+impl core::fmt::Display for ParseEventFlagsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("invalid event flags")
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for ParseEventFlagsError {}
+
+/// Parses the format written by `Display`, ignoring case and whitespace around each flag, and
+/// also accepting `,` as a separator
+impl core::str::FromStr for EventFlags {
+    type Err = ParseEventFlagsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = Self::empty();
+        for name in s.split(['|', ',']).map(str::trim) {
+            if name.eq_ignore_ascii_case("none") {
+                continue;
+            }
+            if let Some(hex) = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")) {
+                let bits = u32::from_str_radix(hex, 16).map_err(|_| ParseEventFlagsError(()))?;
+                flags |= Self::from_bits_retain(bits);
+                continue;
+            }
+            let (_, flag) = Self::all()
+                .iter_names()
+                .find(|(known, _)| known.eq_ignore_ascii_case(name))
+                .ok_or(ParseEventFlagsError(()))?;
+            flags |= flag;
+        }
+        Ok(flags)
+    }
+}
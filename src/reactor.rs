@@ -0,0 +1,300 @@
+//! Running an event queue on a background thread, calling back on events.
+//!
+//! ```ignore
+//! let reactor = Reactor::spawn()?;
+//! reactor.register(fd, EventFlags::READ, |flags| println!("fd is ready: {flags:?}"))?;
+//! ```
+//!
+//! The same thread can wake futures, so async code can wait for a single fd without an
+//! executor integrated with the queue:
+//!
+//! ```ignore
+//! let flags = event::ready(fd, EventFlags::READ).await?;
+//! ```
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+use std::boxed::Box;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::thread;
+
+use libredox::errno::EINTR;
+
+use crate::wrappers::{io_error, reserved};
+use crate::{EventFlags, EventWaker, RawEventQueue};
+
+type Callback = Arc<Mutex<Box<dyn FnMut(EventFlags) + Send>>>;
+
+struct Shared {
+    queue: RawEventQueue,
+    callbacks: Mutex<BTreeMap<usize, Callback>>,
+    waker: EventWaker,
+    stopped: AtomicBool,
+    handles: AtomicUsize,
+}
+
+/// A background thread waiting on an event queue
+pub struct Reactor;
+
+impl Reactor {
+    /// Start the reactor thread, returning the handle used to register fds with it
+    ///
+    /// The thread exits once every handle has been dropped, or [`shutdown`](ReactorHandle::shutdown)
+    /// is called.
+    pub fn spawn() -> io::Result<ReactorHandle> {
+        let shared = Arc::new(Shared {
+            queue: RawEventQueue::new().map_err(io_error)?,
+            callbacks: Mutex::new(BTreeMap::new()),
+            waker: EventWaker::new()?,
+            stopped: AtomicBool::new(false),
+            handles: AtomicUsize::new(1),
+        });
+        let waker = shared.waker.as_raw_fd();
+        shared
+            .queue
+            .subscribe(waker, reserved::WAKER, EventFlags::READ)
+            .map_err(io_error)?;
+
+        let thread_shared = Arc::clone(&shared);
+        thread::Builder::new()
+            .name("redox_event reactor".into())
+            .spawn(move || {
+                run(&thread_shared);
+                // Callbacks holding handles after a shutdown would keep the state alive.
+                thread_shared
+                    .callbacks
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clear();
+            })?;
+        Ok(ReactorHandle { shared })
+    }
+}
+
+fn run(shared: &Shared) {
+    while !shared.stopped.load(Ordering::Acquire) {
+        let event = match shared.queue.next_event() {
+            Ok(event) => event,
+            Err(err) if err.errno() == EINTR => continue,
+            Err(_) => break,
+        };
+        if event.user_data == reserved::WAKER {
+            let _ = shared.waker.wake_count();
+            continue;
+        }
+        // The map must not stay locked while calling back, which may register more fds.
+        let callback = shared
+            .callbacks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&event.fd)
+            .cloned();
+        if let Some(callback) = callback {
+            let mut callback = callback.lock().unwrap_or_else(PoisonError::into_inner);
+            callback(EventFlags::from_bits_retain(event.flags));
+        }
+    }
+}
+
+/// A cloneable handle to a [`Reactor`], for registering fds from any thread
+pub struct ReactorHandle {
+    shared: Arc<Shared>,
+}
+
+impl ReactorHandle {
+    /// Call `callback` on the reactor thread whenever `fd` is ready for `flags`
+    ///
+    /// This replaces any previous registration of `fd`. A callback that needs the reactor should
+    /// capture a [`WeakReactorHandle`], as a handle it owns keeps the reactor running.
+    pub fn register(
+        &self,
+        fd: usize,
+        flags: EventFlags,
+        callback: impl FnMut(EventFlags) + Send + 'static,
+    ) -> io::Result<()> {
+        let callback: Callback = Arc::new(Mutex::new(Box::new(callback)));
+        self.callbacks().insert(fd, callback);
+        if let Err(err) = self.shared.queue.subscribe(fd, fd, flags) {
+            self.callbacks().remove(&fd);
+            return Err(io_error(err));
+        }
+        Ok(())
+    }
+    /// Stop calling back for `fd`
+    ///
+    /// A callback that is already running when this is called from another thread finishes
+    /// normally.
+    pub fn deregister(&self, fd: usize) -> io::Result<()> {
+        self.shared.queue.unsubscribe(fd).map_err(io_error)?;
+        self.callbacks().remove(&fd);
+        Ok(())
+    }
+    /// Resolve once `fd` is ready for `flags`, with the flags it is ready for
+    ///
+    /// `fd` is registered on the first poll, replacing any previous registration, and
+    /// deregistered once the future completes or is dropped.
+    pub fn ready(&self, fd: usize, flags: EventFlags) -> Ready {
+        Ready::new(fd, flags, Some(self.clone()))
+    }
+    /// A handle that does not keep the reactor running
+    pub fn downgrade(&self) -> WeakReactorHandle {
+        WeakReactorHandle {
+            shared: Arc::downgrade(&self.shared),
+        }
+    }
+    /// Make the reactor thread exit after the callback it is running, if any
+    pub fn shutdown(&self) {
+        self.shared.stopped.store(true, Ordering::Release);
+        let _ = self.shared.waker.wake();
+    }
+    fn callbacks(&self) -> std::sync::MutexGuard<'_, BTreeMap<usize, Callback>> {
+        self.shared
+            .callbacks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+impl Clone for ReactorHandle {
+    fn clone(&self) -> Self {
+        self.shared.handles.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+impl Drop for ReactorHandle {
+    fn drop(&mut self) {
+        if self.shared.handles.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shutdown();
+        }
+    }
+}
+
+/// A handle to a [`Reactor`] that does not keep it running, created by
+/// [`ReactorHandle::downgrade`]
+#[derive(Clone)]
+pub struct WeakReactorHandle {
+    shared: Weak<Shared>,
+}
+
+impl WeakReactorHandle {
+    /// A handle to the reactor, unless every handle has been dropped
+    pub fn upgrade(&self) -> Option<ReactorHandle> {
+        let shared = self.shared.upgrade()?;
+        let mut handles = shared.handles.load(Ordering::Relaxed);
+        loop {
+            // Once the count reaches zero the reactor has been shut down for good.
+            if handles == 0 {
+                return None;
+            }
+            match shared.handles.compare_exchange_weak(
+                handles,
+                handles + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(ReactorHandle { shared }),
+                Err(actual) => handles = actual,
+            }
+        }
+    }
+}
+
+std::thread_local! {
+    static LOCAL: RefCell<Option<ReactorHandle>> = const { RefCell::new(None) };
+}
+
+/// Resolve once `fd` is ready for `flags`, using a reactor owned by the polling thread
+///
+/// The reactor is spawned the first time a future returned by this is polled on a thread, and
+/// stops once the thread has exited and the futures using it are gone. See
+/// [`ReactorHandle::ready`].
+pub fn ready(fd: usize, flags: EventFlags) -> Ready {
+    Ready::new(fd, flags, None)
+}
+fn local_reactor() -> io::Result<ReactorHandle> {
+    LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        if let Some(ref reactor) = *local {
+            return Ok(reactor.clone());
+        }
+        Ok(local.insert(Reactor::spawn()?).clone())
+    })
+}
+
+/// A future resolving once an fd is ready, returned by [`ready`] and [`ReactorHandle::ready`]
+pub struct Ready {
+    fd: usize,
+    flags: EventFlags,
+    // None until the first poll for the thread-local reactor.
+    reactor: Option<ReactorHandle>,
+    state: Arc<Mutex<ReadyState>>,
+    registered: bool,
+}
+
+#[derive(Default)]
+struct ReadyState {
+    flags: Option<EventFlags>,
+    waker: Option<Waker>,
+}
+
+impl Ready {
+    fn new(fd: usize, flags: EventFlags, reactor: Option<ReactorHandle>) -> Self {
+        Self {
+            fd,
+            flags,
+            reactor,
+            state: Arc::default(),
+            registered: false,
+        }
+    }
+    fn deregister(&mut self) {
+        if let (true, Some(reactor)) = (self.registered, &self.reactor) {
+            let _ = reactor.deregister(self.fd);
+        }
+        self.registered = false;
+    }
+}
+impl Future for Ready {
+    type Output = io::Result<EventFlags>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        {
+            let mut state = this.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(flags) = state.flags {
+                drop(state);
+                this.deregister();
+                return Poll::Ready(Ok(flags));
+            }
+            state.waker = Some(cx.waker().clone());
+        }
+        if !this.registered {
+            let reactor = match this.reactor {
+                Some(ref reactor) => reactor,
+                None => this.reactor.insert(local_reactor()?),
+            };
+            let state = Arc::clone(&this.state);
+            reactor.register(this.fd, this.flags, move |flags| {
+                let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+                state.flags = Some(state.flags.unwrap_or_default() | flags);
+                let waker = state.waker.take();
+                drop(state);
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            })?;
+            this.registered = true;
+        }
+        Poll::Pending
+    }
+}
+impl Drop for Ready {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
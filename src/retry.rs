@@ -0,0 +1,106 @@
+//! Retrying subscriptions that fail for lack of resources.
+use core::fmt;
+use core::time::Duration;
+use std::hash::{BuildHasher, RandomState};
+use std::thread;
+
+use libredox::errno::{EAGAIN, EINTR, ENOMEM, ENOSPC};
+use libredox::error::Error;
+
+use crate::{EventFlags, EventQueue, UserData};
+
+/// How [`EventQueue::subscribe_with_retry`] retries after transient errors
+///
+/// The delay starts at `initial_delay` and is multiplied by `multiplier` after each attempt, up
+/// to `max_delay`. With `jitter`, a random delay between half and all of it is used instead, so
+/// that many drivers retrying at once do not do so in lockstep.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: u32,
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(100),
+            multiplier: 2,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether `error` may go away by itself, and is worth retrying
+    pub fn is_transient(error: Error) -> bool {
+        matches!(error.errno(), EINTR | ENOMEM | EAGAIN | ENOSPC)
+    }
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(self.multiplier.saturating_pow(attempt))
+            .min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let random = RandomState::new().hash_one(attempt);
+        delay / 2 + delay.mul_f64((random % 1024) as f64 / 2048.0)
+    }
+}
+
+/// The error returned by [`EventQueue::subscribe_with_retry`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryError {
+    /// The error of the last attempt
+    pub error: Error,
+    /// The number of attempts made, including the last one
+    pub attempts: u32,
+    /// Whether `error` was transient, in which case the attempts were exhausted
+    pub transient: bool,
+}
+
+impl fmt::Display for RetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "subscribing failed after {} attempts: ", self.attempts)?;
+        fmt::Debug::fmt(&self.error, f)
+    }
+}
+impl std::error::Error for RetryError {}
+
+impl<U: UserData> EventQueue<U> {
+    /// Subscribe to events produced by `fd`, retrying after transient errors as set by `policy`
+    ///
+    /// Errors that are not transient are returned right away. The calling thread sleeps between
+    /// attempts.
+    pub fn subscribe_with_retry(
+        &self,
+        fd: usize,
+        data: U,
+        flags: EventFlags,
+        policy: RetryPolicy,
+    ) -> Result<(), RetryError> {
+        let mut attempts = 0;
+        loop {
+            let error = match self.subscribe(fd, data, flags) {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+            attempts += 1;
+
+            let transient = RetryPolicy::is_transient(error);
+            if !transient || attempts >= policy.max_attempts {
+                return Err(RetryError {
+                    error,
+                    attempts,
+                    transient,
+                });
+            }
+            thread::sleep(policy.delay(attempts - 1));
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Allocation checking for the realtime dispatch path.
+//!
+//! Setting up a queue may allocate, e.g. when the subscription table grows in
+//! [`EventQueue::subscribe`](crate::EventQueue::subscribe). Once subscriptions are in place, the
+//! steady-state path through [`EventQueue::next_event`](crate::EventQueue::next_event) (and its
+//! iterators and `dispatch`) performs no heap allocation, including the call to a filter set with
+//! `set_filter`, as long as the filter itself does not allocate. Splitting events for fds with
+//! several interests uses space reserved when the interests are added.
+//!
+//! [`EventLoop`](crate::EventLoop) reuses the buffer it sorts control events with, so it only
+//! allocates when a batch is larger than any before it.
+//!
+//! To enforce this, install [`RtCheckAlloc`] as the global allocator. Any allocation or
+//! deallocation made by a thread while it is dispatching, or inside [`assert_no_alloc`], will
+//! then abort the process after writing a message to stderr:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: event::rt::RtCheckAlloc<std::alloc::System> = event::rt::RtCheckAlloc(std::alloc::System);
+//! ```
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+
+std::thread_local! {
+    static HOT_PATH_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Global allocator wrapper that aborts when called from the realtime dispatch path
+pub struct RtCheckAlloc<A>(pub A);
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for RtCheckAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        check();
+        self.0.alloc(layout)
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        check();
+        self.0.alloc_zeroed(layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        check();
+        self.0.realloc(ptr, layout, new_size)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        check();
+        self.0.dealloc(ptr, layout)
+    }
+}
+
+fn check() {
+    // The thread-local may already have been destroyed during thread exit, which is never part
+    // of the hot path.
+    let in_hot_path = HOT_PATH_DEPTH
+        .try_with(|depth| depth.get() > 0)
+        .unwrap_or(false);
+
+    // Unwinding out of an allocator is undefined behavior, and formatting a panic message would
+    // allocate again, so write a fixed message and abort instead.
+    if in_hot_path {
+        let _ = std::io::Write::write_all(
+            &mut std::io::stderr(),
+            b"heap allocation in realtime event dispatch path\n",
+        );
+        std::process::abort();
+    }
+}
+
+/// Marks the current thread as being inside the realtime dispatch path while alive
+pub(crate) struct HotPath(());
+
+impl HotPath {
+    pub(crate) fn enter() -> Self {
+        HOT_PATH_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        Self(())
+    }
+}
+impl Drop for HotPath {
+    fn drop(&mut self) {
+        HOT_PATH_DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+    }
+}
+
+/// Run `f`, aborting if it allocates while [`RtCheckAlloc`] is the global allocator
+///
+/// Useful for covering event handlers, which are outside of this crate's own dispatch path.
+pub fn assert_no_alloc<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = HotPath::enter();
+    f()
+}
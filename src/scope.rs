@@ -0,0 +1,92 @@
+//! Scoped fd tasks, which cannot outlive the state they borrow.
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::ops::ControlFlow;
+
+use libredox::errno::EEXIST;
+use libredox::error::{Error, Result};
+
+use crate::{EventFlags, RawEventQueue};
+
+type Task<'env> = Box<dyn FnMut(&EventScope<'env>, EventFlags) -> ControlFlow<()> + 'env>;
+
+struct TaskSlot<'env> {
+    fd: usize,
+    // Taken while the task is running, and for good once it has finished.
+    task: Option<Task<'env>>,
+}
+
+/// A set of fd tasks that all finish before [`EventScope::run`] returns
+///
+/// Since the tasks cannot outlive the scope, they can borrow local state, much like
+/// `std::thread::scope`.
+pub struct EventScope<'env> {
+    queue: RawEventQueue,
+    tasks: RefCell<Vec<TaskSlot<'env>>>,
+    // The fds of the tasks that have not finished yet.
+    live: RefCell<BTreeSet<usize>>,
+}
+
+impl<'env> EventScope<'env> {
+    /// Call `f` to spawn tasks, then dispatch events to them until all of them have finished
+    ///
+    /// Every task is unsubscribed and dropped by the time this returns, including when an error
+    /// is returned.
+    pub fn run<R>(f: impl FnOnce(&EventScope<'env>) -> R) -> Result<R> {
+        let scope = EventScope {
+            queue: RawEventQueue::new()?,
+            tasks: RefCell::new(Vec::new()),
+            live: RefCell::new(BTreeSet::new()),
+        };
+        let ret = f(&scope);
+
+        while !scope.live.borrow().is_empty() {
+            let event = scope.queue.next_event()?;
+            let index = event.user_data;
+
+            let task = scope
+                .tasks
+                .borrow_mut()
+                .get_mut(index)
+                .and_then(|slot| slot.task.take());
+            let Some(mut task) = task else {
+                continue;
+            };
+
+            let flow = task(&scope, EventFlags::from_bits_retain(event.flags));
+            let mut tasks = scope.tasks.borrow_mut();
+            match flow {
+                ControlFlow::Continue(()) => tasks[index].task = Some(task),
+                ControlFlow::Break(()) => {
+                    scope.live.borrow_mut().remove(&tasks[index].fd);
+                    scope.queue.unsubscribe(tasks[index].fd)?;
+                }
+            }
+        }
+        Ok(ret)
+    }
+    /// Call `handler` with the events produced by `fd`, until it breaks
+    ///
+    /// The handler can spawn further tasks through the scope it is passed. Fails with `EEXIST` if
+    /// a task for `fd` has not finished yet, as the fd has only one subscription for both.
+    pub fn spawn_fd_task(
+        &self,
+        fd: usize,
+        flags: EventFlags,
+        handler: impl FnMut(&EventScope<'env>, EventFlags) -> ControlFlow<()> + 'env,
+    ) -> Result<()> {
+        if self.live.borrow().contains(&fd) {
+            return Err(Error::new(EEXIST));
+        }
+        let mut tasks = self.tasks.borrow_mut();
+        self.queue.subscribe(fd, tasks.len(), flags)?;
+        tasks.push(TaskSlot {
+            fd,
+            task: Some(Box::new(handler)),
+        });
+        self.live.borrow_mut().insert(fd);
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! Subscriptions whose user data borrows from the caller's state.
+//!
+//! User data that is a reference would be unsound, since the queue could report an event for it
+//! after the referent is gone. A [`ScopedQueue`] only exists within [`ScopedQueue::scope`], and
+//! the state it borrows outlives the scope, so events always refer to live state:
+//!
+//! ```ignore
+//! let sockets = [Socket::new(a), Socket::new(b)];
+//! ScopedQueue::scope(|queue| {
+//!     let first = queue.register(sockets[0].fd(), &sockets[0], EventFlags::READ)?;
+//!     let _second = queue.register(sockets[1].fd(), &sockets[1], EventFlags::READ)?;
+//!     let (socket, flags) = queue.next_event()?;
+//!     queue.deregister(first)?;
+//!     Ok(())
+//! })??;
+//! ```
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::marker::PhantomData;
+
+use libredox::error::Result;
+
+use crate::{EventFlags, RawEventQueue};
+
+// Makes `'scope` invariant, so tokens of one scope cannot be passed to the queue of another.
+type Brand<'scope> = PhantomData<&'scope mut &'scope ()>;
+
+struct Slot<'env, T: ?Sized> {
+    fd: usize,
+    data: &'env T,
+}
+
+/// A queue whose subscriptions carry references to state outliving it
+pub struct ScopedQueue<'scope, 'env: 'scope, T: ?Sized> {
+    queue: RawEventQueue,
+    // Indexed by the user data of the subscription.
+    slots: RefCell<Vec<Option<Slot<'env, T>>>>,
+    _scope: Brand<'scope>,
+}
+
+/// A subscription of a [`ScopedQueue`], which can only be used within the scope it was made in
+#[must_use = "a registration can only be removed with its token"]
+pub struct Scoped<'scope, T: ?Sized> {
+    index: usize,
+    fd: usize,
+    _scope: Brand<'scope>,
+    _data: PhantomData<fn() -> T>,
+}
+
+impl<T: ?Sized> Scoped<'_, T> {
+    #[inline]
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+}
+
+impl<'scope, 'env, T: ?Sized> ScopedQueue<'scope, 'env, T> {
+    /// Create a queue for the duration of `f`, which is destroyed with all of its subscriptions
+    /// when `f` returns
+    pub fn scope<R>(f: impl for<'s> FnOnce(&'s ScopedQueue<'s, 'env, T>) -> R) -> Result<R> {
+        let queue = ScopedQueue {
+            queue: RawEventQueue::new()?,
+            slots: RefCell::new(Vec::new()),
+            _scope: PhantomData,
+        };
+        Ok(f(&queue))
+    }
+    /// Subscribe to events produced by `fd`, which are reported with `data`
+    pub fn register(
+        &'scope self,
+        fd: usize,
+        data: &'env T,
+        flags: EventFlags,
+    ) -> Result<Scoped<'scope, T>> {
+        let mut slots = self.slots.borrow_mut();
+        let index = slots
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(slots.len());
+        self.queue.subscribe(fd, index, flags)?;
+        let slot = Some(Slot { fd, data });
+        match slots.get_mut(index) {
+            Some(free) => *free = slot,
+            None => slots.push(slot),
+        }
+        Ok(Scoped {
+            index,
+            fd,
+            _scope: PhantomData,
+            _data: PhantomData,
+        })
+    }
+    /// Unsubscribe the fd of `token`, returning its data
+    pub fn deregister(&'scope self, token: Scoped<'scope, T>) -> Result<&'env T> {
+        self.queue.unsubscribe(token.fd)?;
+        let slot = self.slots.borrow_mut()[token.index]
+            .take()
+            .expect("tokens are only created for occupied slots");
+        Ok(slot.data)
+    }
+    /// Block until the next event, returning the data its fd was registered with
+    pub fn next_event(&self) -> Result<(&'env T, EventFlags)> {
+        loop {
+            let event = self.queue.next_event()?;
+            let slots = self.slots.borrow();
+            // Events queued before an fd was deregistered are dropped.
+            let Some(Some(slot)) = slots.get(event.user_data) else {
+                continue;
+            };
+            if slot.fd == event.fd {
+                return Ok((slot.data, EventFlags::from_bits_retain(event.flags)));
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Letting signals interrupt a wait, and only a wait.
+//!
+//! Signals that are handled by setting a flag for the main loop race with the loop: a signal
+//! arriving just before the loop blocks is only noticed after the next event. The fix is to keep
+//! these signals blocked, and unblock them atomically with waiting, which [`SignalScope`] does:
+//!
+//! ```ignore
+//! let scope = SignalScope::new(&[Signal::Interrupt, Signal::Terminate])?;
+//! loop {
+//!     match scope.wait(&queue, &mut buf, None)? {
+//!         SignalWait::Events(events) => { /* handle events */ }
+//!         SignalWait::Interrupted => { /* check the flags set by the signal handlers */ }
+//!     }
+//! }
+//! ```
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+
+use libredox::data::SigSet;
+use libredox::error::Result;
+use libredox::flag::{SIG_BLOCK, SIG_SETMASK};
+
+use crate::{RawEvent, RawEventQueue, Timeout, WaitFlags};
+
+/// A signal that a [`SignalScope`] lets through while waiting
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[repr(i32)]
+pub enum Signal {
+    Hangup = libredox::flag::SIGHUP,
+    Interrupt = libredox::flag::SIGINT,
+    Quit = libredox::flag::SIGQUIT,
+    Alarm = libredox::flag::SIGALRM,
+    Terminate = libredox::flag::SIGTERM,
+    User1 = libredox::flag::SIGUSR1,
+    User2 = libredox::flag::SIGUSR2,
+    Child = libredox::flag::SIGCHLD,
+    Pipe = libredox::flag::SIGPIPE,
+    WindowChange = libredox::flag::SIGWINCH,
+}
+
+impl Signal {
+    fn mask(self) -> SigSet {
+        1 << (self as i32 - 1)
+    }
+}
+
+/// The outcome of [`SignalScope::wait`]
+#[derive(Debug)]
+pub enum SignalWait<'a> {
+    /// The events that were read, which is none if the timeout expired
+    Events(&'a mut [RawEvent]),
+    /// One of the signals of the scope was delivered while waiting
+    Interrupted,
+}
+
+/// Blocks a set of signals on the current thread, except while waiting on an event queue
+///
+/// The signal mask of the thread is restored when the scope is dropped. Scopes can be nested,
+/// as long as they are dropped in the reverse order of their creation.
+pub struct SignalScope {
+    saved: SigSet,
+    signals: SigSet,
+    // The signal mask belongs to the thread that created the scope.
+    _thread: PhantomData<*const ()>,
+}
+
+impl SignalScope {
+    /// Block `signals` on the current thread, until they are let through by a wait
+    pub fn new(signals: &[Signal]) -> Result<Self> {
+        let signals = signals.iter().fold(0, |mask, signal| mask | signal.mask());
+        let mut saved = 0;
+        libredox::call::sigprocmask(SIG_BLOCK, Some(&signals), Some(&mut saved))?;
+        Ok(Self {
+            saved,
+            signals,
+            _thread: PhantomData,
+        })
+    }
+    /// Whether `signal` is let through while waiting
+    #[inline]
+    pub fn contains(&self, signal: Signal) -> bool {
+        self.signals & signal.mask() != 0
+    }
+    /// The signal mask of the thread while it waits
+    ///
+    /// This is the mask from before the scope was created, without the signals of the scope.
+    #[inline]
+    pub fn wait_mask(&self) -> SigSet {
+        self.saved & !self.signals
+    }
+    /// Block until events are available, `timeout` has passed, or a signal of the scope is
+    /// delivered
+    ///
+    /// See [`RawEventQueue::wait`].
+    pub fn wait<'a>(
+        &self,
+        queue: &RawEventQueue,
+        buf: &'a mut [MaybeUninit<RawEvent>],
+        timeout: Option<Timeout>,
+    ) -> Result<SignalWait<'a>> {
+        match queue.wait_with_sigmask(buf, timeout, WaitFlags::NONE, Some(&self.wait_mask())) {
+            Ok(events) => Ok(SignalWait::Events(events)),
+            Err(err) if err.is_interrupt() => Ok(SignalWait::Interrupted),
+            Err(err) => Err(err),
+        }
+    }
+}
+impl Drop for SignalScope {
+    fn drop(&mut self) {
+        let _ = libredox::call::sigprocmask(SIG_SETMASK, Some(&self.saved), None);
+    }
+}
@@ -0,0 +1,78 @@
+//! Routing events to per-source state machines.
+use libredox::error::Result;
+
+use crate::{Event, EventLoop, EventQueue, UserData};
+
+/// A state machine driven by the events of one or more subscriptions
+pub trait StateMachine<U: UserData> {
+    fn on_event(&mut self, event: Event<U>);
+}
+impl<U: UserData, F: FnMut(Event<U>)> StateMachine<U> for F {
+    fn on_event(&mut self, event: Event<U>) {
+        self(event)
+    }
+}
+
+/// The state machines of a [`StateMachineLoop`], looked up by user data
+///
+/// Implementing [`machine`](Self::machine) with a `match` on an enum declared with
+/// [`user_data!`](crate::user_data) makes the compiler check that every kind of event has a
+/// state machine:
+///
+/// ```ignore
+/// impl Machines<Token> for Daemon {
+///     fn machine(&mut self, data: Token) -> &mut dyn StateMachine<Token> {
+///         match data {
+///             Token::Listener => &mut self.listener,
+///             Token::Config => &mut self.config,
+///         }
+///     }
+/// }
+/// ```
+pub trait Machines<U: UserData> {
+    fn machine(&mut self, data: U) -> &mut dyn StateMachine<U>;
+}
+
+/// An [`EventLoop`] that passes each event to the state machine for its user data
+pub struct StateMachineLoop<U: UserData, S: Machines<U>> {
+    inner: EventLoop<U>,
+    machines: S,
+}
+
+impl<U: UserData, S: Machines<U>> StateMachineLoop<U, S> {
+    /// Create a loop around a new event queue
+    pub fn new(machines: S) -> Result<Self> {
+        Ok(Self::with_queue(EventQueue::new()?, machines))
+    }
+    /// Create a loop around an existing event queue
+    pub fn with_queue(queue: EventQueue<U>, machines: S) -> Self {
+        Self {
+            inner: EventLoop::with_queue(queue),
+            machines,
+        }
+    }
+    #[inline]
+    pub fn event_loop(&self) -> &EventLoop<U> {
+        &self.inner
+    }
+    #[inline]
+    pub fn event_loop_mut(&mut self) -> &mut EventLoop<U> {
+        &mut self.inner
+    }
+    #[inline]
+    pub fn machines(&self) -> &S {
+        &self.machines
+    }
+    #[inline]
+    pub fn machines_mut(&mut self) -> &mut S {
+        &mut self.machines
+    }
+    /// Run a single iteration of the loop, routing every ready event to its state machine
+    ///
+    /// See [`EventLoop::run_once`].
+    pub fn run_once(&mut self) -> Result<usize> {
+        let machines = &mut self.machines;
+        self.inner
+            .run_once(|_, event| machines.machine(event.user_data).on_event(event))
+    }
+}
@@ -0,0 +1,101 @@
+//! A dispatcher for drivers that cannot allocate.
+use core::mem::MaybeUninit;
+
+use libredox::errno::{ENOENT, ENOSPC};
+use libredox::error::{Error, Result};
+
+use crate::{Event, EventFlags, RawEvent, RawEventQueue, UserData, WaitFlags};
+
+#[derive(Clone, Copy)]
+struct Slot<U> {
+    fd: usize,
+    data: U,
+}
+
+/// An event loop with room for `N` subscriptions, reading up to `B` events per syscall
+///
+/// All state is inline, so this works without `alloc`, and a loop iteration does a bounded
+/// amount of work: one wait, and at most `B` calls of the handler. The kernel is given the index
+/// of each subscription as user data, so events are routed without searching.
+pub struct StaticLoop<const N: usize, U: UserData, const B: usize = 16> {
+    queue: RawEventQueue,
+    slots: [Option<Slot<U>>; N],
+}
+
+impl<const N: usize, U: UserData, const B: usize> StaticLoop<N, U, B> {
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_queue(RawEventQueue::new()?))
+    }
+    pub fn with_queue(queue: RawEventQueue) -> Self {
+        Self {
+            queue,
+            slots: [None; N],
+        }
+    }
+    /// Subscribe to events produced by `fd`, or change its subscription
+    ///
+    /// Fails with `ENOSPC` if all `N` subscriptions are in use.
+    pub fn subscribe(&mut self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        let index = match self.find(fd) {
+            Some(index) => index,
+            None => self
+                .slots
+                .iter()
+                .position(Option::is_none)
+                .ok_or(Error::new(ENOSPC))?,
+        };
+        self.queue.subscribe(fd, index, flags)?;
+        self.slots[index] = Some(Slot { fd, data });
+        Ok(())
+    }
+    /// Unsubscribe from events produced by `fd`
+    ///
+    /// Fails with `ENOENT` if `fd` is not subscribed.
+    pub fn unsubscribe(&mut self, fd: usize) -> Result<()> {
+        let index = self.find(fd).ok_or(Error::new(ENOENT))?;
+        self.queue.unsubscribe(fd)?;
+        self.slots[index] = None;
+        Ok(())
+    }
+    fn find(&self, fd: usize) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.is_some_and(|slot| slot.fd == fd))
+    }
+    /// The number of subscriptions in use
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Block until events are available, and pass up to `B` of them to `f`
+    ///
+    /// Events for fds that were unsubscribed in the meantime are skipped. Returns the number of
+    /// events passed to `f`.
+    pub fn run_once(&mut self, mut f: impl FnMut(Event<U>)) -> Result<usize> {
+        let mut buf = [MaybeUninit::<RawEvent>::uninit(); B];
+        let events = self.queue.wait(&mut buf, None, WaitFlags::NONE)?;
+
+        let mut count = 0;
+        for raw in events.iter() {
+            let Some(Some(slot)) = self.slots.get(raw.user_data) else {
+                continue;
+            };
+            if slot.fd != raw.fd {
+                continue;
+            }
+            f(Event {
+                user_data: slot.data,
+                flags: EventFlags::from_bits_retain(raw.flags),
+                fd: raw.fd,
+            });
+            count += 1;
+        }
+        Ok(count)
+    }
+    #[inline]
+    pub fn queue(&self) -> &RawEventQueue {
+        &self.queue
+    }
+}
@@ -0,0 +1,69 @@
+//! Counting the syscalls made by a queue, for performance analysis.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of syscalls a queue has made, by kind
+///
+/// Failed syscalls are counted too. The creating syscall is only counted for queues created with
+/// `new`, not for adopted fds.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SyscallStats {
+    pub creates: usize,
+    pub ctls: usize,
+    pub waits: usize,
+    pub destroys: usize,
+    /// The total number of events returned by all waits
+    pub events: usize,
+}
+
+impl SyscallStats {
+    /// The average number of events returned per wait, which shows how well waits are batched
+    pub fn events_per_wait(&self) -> f64 {
+        if self.waits == 0 {
+            return 0.0;
+        }
+        self.events as f64 / self.waits as f64
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    creates: AtomicUsize,
+    ctls: AtomicUsize,
+    waits: AtomicUsize,
+    destroys: AtomicUsize,
+    events: AtomicUsize,
+}
+
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            creates: AtomicUsize::new(0),
+            ctls: AtomicUsize::new(0),
+            waits: AtomicUsize::new(0),
+            destroys: AtomicUsize::new(0),
+            events: AtomicUsize::new(0),
+        }
+    }
+    pub(crate) fn create(&self) {
+        self.creates.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(crate) fn ctl(&self) {
+        self.ctls.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(crate) fn wait(&self, events: usize) {
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.events.fetch_add(events, Ordering::Relaxed);
+    }
+    pub(crate) fn destroy(&self) {
+        self.destroys.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(crate) fn snapshot(&self) -> SyscallStats {
+        SyscallStats {
+            creates: self.creates.load(Ordering::Relaxed),
+            ctls: self.ctls.load(Ordering::Relaxed),
+            waits: self.waits.load(Ordering::Relaxed),
+            destroys: self.destroys.load(Ordering::Relaxed),
+            events: self.events.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Subscribing to the standard streams of the current process.
+use libredox::error::Result;
+
+use crate::{EventFlags, EventQueue, UserData};
+
+/// One of the standard streams of the current process, with the readiness it is watched for
+///
+/// Registering a stream switches it to nonblocking mode, so that reading or writing after an
+/// event never blocks the loop. The mode belongs to the open file rather than the fd, so it is
+/// also seen by other processes sharing the stream, e.g. the shell that started this one; it is
+/// restored once the [`StdRegistration`] is dropped. When stdin is a terminal in canonical mode,
+/// events are delivered once a whole line has been entered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StdStream {
+    fd: usize,
+    flags: EventFlags,
+}
+
+impl StdStream {
+    /// Standard input, watched for input becoming available
+    pub const STDIN: Self = Self::new(0, EventFlags::READ);
+    /// Standard output, watched for becoming writable, e.g. after a full pipe drains
+    pub const STDOUT: Self = Self::new(1, EventFlags::WRITE);
+    /// Standard error, watched for becoming writable
+    pub const STDERR: Self = Self::new(2, EventFlags::WRITE);
+
+    const fn new(fd: usize, flags: EventFlags) -> Self {
+        Self { fd, flags }
+    }
+    #[inline]
+    pub const fn fd(&self) -> usize {
+        self.fd
+    }
+    #[inline]
+    pub const fn flags(&self) -> EventFlags {
+        self.flags
+    }
+    /// Subscribe `queue` to the readiness of this stream until the registration is dropped
+    pub fn register<U: UserData>(
+        self,
+        queue: &EventQueue<U>,
+        data: U,
+    ) -> Result<StdRegistration<'_, U>> {
+        queue.subscribe_nonblocking(self.fd, data, self.flags)?;
+        Ok(StdRegistration { queue, fd: self.fd })
+    }
+}
+
+/// A subscription of a standard stream, which is removed when dropped
+///
+/// Dropping it also switches the stream back to blocking mode, if registering changed it.
+pub struct StdRegistration<'q, U: UserData> {
+    queue: &'q EventQueue<U>,
+    fd: usize,
+}
+
+impl<U: UserData> StdRegistration<'_, U> {
+    #[inline]
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+}
+impl<U: UserData> Drop for StdRegistration<'_, U> {
+    fn drop(&mut self) {
+        let _ = self.queue.unsubscribe(self.fd);
+    }
+}
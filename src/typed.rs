@@ -0,0 +1,103 @@
+//! Subscriptions whose interest is part of their type.
+//!
+//! A [`TypedRegistration`] only has the methods for the interest it was registered with, so
+//! handling write readiness of an fd that was only subscribed for reading, or the reverse, is
+//! caught at compile time:
+//!
+//! ```ignore
+//! let socket = TypedRegistration::<Read, _>::new(&queue, fd, Token::Socket)?;
+//! let event = queue.next_event()?;
+//! socket.on_readable(&event, || { /* read */ });
+//! socket.on_writable(&event, || {}); // error: `Read` is not `Writable`
+//! ```
+use core::marker::PhantomData;
+
+use libredox::error::Result;
+
+use crate::{Event, EventFlags, EventQueue, UserData};
+
+/// The events a [`TypedRegistration`] is subscribed to
+pub trait Interest {
+    const FLAGS: EventFlags;
+}
+/// An interest including read readiness
+pub trait Readable: Interest {}
+/// An interest including write readiness
+pub trait Writable: Interest {}
+
+/// Interest in read readiness only
+pub struct Read;
+/// Interest in write readiness only
+pub struct Write;
+/// Interest in both read and write readiness
+pub struct ReadWrite;
+
+impl Interest for Read {
+    const FLAGS: EventFlags = EventFlags::READ;
+}
+impl Interest for Write {
+    const FLAGS: EventFlags = EventFlags::WRITE;
+}
+impl Interest for ReadWrite {
+    const FLAGS: EventFlags = EventFlags::READ.union(EventFlags::WRITE);
+}
+impl Readable for Read {}
+impl Readable for ReadWrite {}
+impl Writable for Write {}
+impl Writable for ReadWrite {}
+
+/// A subscription of an fd with interest `I`, which is removed when dropped
+pub struct TypedRegistration<'q, I: Interest, U: UserData> {
+    queue: &'q EventQueue<U>,
+    fd: usize,
+    _interest: PhantomData<I>,
+}
+
+impl<'q, I: Interest, U: UserData> TypedRegistration<'q, I, U> {
+    /// Subscribe to the events of `fd` that `I` is interested in
+    pub fn new(queue: &'q EventQueue<U>, fd: usize, data: U) -> Result<Self> {
+        queue.subscribe(fd, data, I::FLAGS)?;
+        Ok(Self {
+            queue,
+            fd,
+            _interest: PhantomData,
+        })
+    }
+    #[inline]
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+    /// Change the interest, keeping the subscription
+    pub fn reregister<J: Interest>(self, data: U) -> Result<TypedRegistration<'q, J, U>> {
+        self.queue.subscribe(self.fd, data, J::FLAGS)?;
+        let queue = self.queue;
+        let fd = self.fd;
+        core::mem::forget(self);
+
+        Ok(TypedRegistration {
+            queue,
+            fd,
+            _interest: PhantomData,
+        })
+    }
+    fn is_for(&self, event: &Event<U>, flags: EventFlags) -> bool {
+        event.fd == self.fd && event.flags.contains(flags)
+    }
+}
+impl<I: Readable, U: UserData> TypedRegistration<'_, I, U> {
+    /// Call `f` if `event` reports this fd as readable
+    pub fn on_readable<R>(&self, event: &Event<U>, f: impl FnOnce() -> R) -> Option<R> {
+        self.is_for(event, EventFlags::READ).then(f)
+    }
+}
+impl<I: Writable, U: UserData> TypedRegistration<'_, I, U> {
+    /// Call `f` if `event` reports this fd as writable
+    pub fn on_writable<R>(&self, event: &Event<U>, f: impl FnOnce() -> R) -> Option<R> {
+        self.is_for(event, EventFlags::WRITE).then(f)
+    }
+}
+impl<I: Interest, U: UserData> Drop for TypedRegistration<'_, I, U> {
+    fn drop(&mut self) {
+        let _ = self.queue.unsubscribe(self.fd);
+    }
+}
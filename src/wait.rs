@@ -0,0 +1,145 @@
+//! Waiting for fds without managing a queue.
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::mem::MaybeUninit;
+use core::time::Duration;
+
+use libredox::error::Result;
+
+use crate::{EventFlags, RawEventQueue};
+#[cfg(feature = "alloc")]
+use crate::{RawEvent, Timeout, WaitFlags};
+
+/// Block until `fd` is readable, or until `timeout` has passed
+///
+/// Returns whether `fd` became readable. A queue is created for the duration of the call, so
+/// code waiting on the same fd repeatedly should keep its own [`EventQueue`](crate::EventQueue).
+pub fn wait_readable(fd: usize, timeout: Option<Duration>) -> Result<bool> {
+    wait_for(fd, EventFlags::READ, timeout)
+}
+/// Block until `fd` is writable, or until `timeout` has passed
+///
+/// Returns whether `fd` became writable. See [`wait_readable`].
+pub fn wait_writable(fd: usize, timeout: Option<Duration>) -> Result<bool> {
+    wait_for(fd, EventFlags::WRITE, timeout)
+}
+fn wait_for(fd: usize, flags: EventFlags, timeout: Option<Duration>) -> Result<bool> {
+    let queue = RawEventQueue::new()?;
+    queue.subscribe(fd, fd, flags)?;
+
+    let event = match timeout {
+        Some(timeout) => queue.next_event_timeout(timeout)?,
+        None => Some(queue.next_event()?),
+    };
+    Ok(event.is_some_and(|event| EventFlags::from_bits_retain(event.flags).intersects(flags)))
+}
+
+/// The readiness of each fd passed to [`poll_many`], by index
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReadinessBitmap {
+    len: usize,
+    read: Vec<u64>,
+    write: Vec<u64>,
+}
+
+#[cfg(feature = "alloc")]
+impl ReadinessBitmap {
+    fn new(len: usize) -> Self {
+        let words = len.div_ceil(64);
+        Self {
+            len,
+            read: alloc::vec![0; words],
+            write: alloc::vec![0; words],
+        }
+    }
+    fn set(&mut self, index: usize, flags: EventFlags) {
+        let (word, bit) = (index / 64, 1 << (index % 64));
+        if flags.contains(EventFlags::READ) {
+            self.read[word] |= bit;
+        }
+        if flags.contains(EventFlags::WRITE) {
+            self.write[word] |= bit;
+        }
+    }
+    fn get(bits: &[u64], index: usize) -> bool {
+        bits.get(index / 64)
+            .is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+    /// The number of fds that were polled
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn is_readable(&self, index: usize) -> bool {
+        Self::get(&self.read, index)
+    }
+    pub fn is_writable(&self, index: usize) -> bool {
+        Self::get(&self.write, index)
+    }
+    /// The readiness of the fd at `index`
+    pub fn flags(&self, index: usize) -> EventFlags {
+        let mut flags = EventFlags::empty();
+        flags.set(EventFlags::READ, self.is_readable(index));
+        flags.set(EventFlags::WRITE, self.is_writable(index));
+        flags
+    }
+    /// The number of fds that are ready in any way
+    pub fn count_ready(&self) -> usize {
+        self.read
+            .iter()
+            .zip(&self.write)
+            .map(|(read, write)| (read | write).count_ones() as usize)
+            .sum()
+    }
+}
+
+/// Return which of `fds` are ready for `flags`, like `select`
+///
+/// If none are ready yet, this blocks until one is, or until `timeout` has passed. Every fd is
+/// subscribed to a queue created for the duration of the call, which reports the fds that are
+/// ready already when subscribing, so all of them are checked with a single wait where possible.
+/// An fd listed several times is reported at each of its indices.
+#[cfg(feature = "alloc")]
+pub fn poll_many(
+    fds: &[usize],
+    flags: EventFlags,
+    timeout: Option<Duration>,
+) -> Result<ReadinessBitmap> {
+    let queue = RawEventQueue::new()?;
+    for (index, &fd) in fds.iter().enumerate() {
+        // Subscribing again would replace the subscription, so only the first one is made.
+        if !fds[..index].contains(&fd) {
+            queue.subscribe(fd, index, flags)?;
+        }
+    }
+
+    let mut ready = ReadinessBitmap::new(fds.len());
+    let mut buf = [MaybeUninit::<RawEvent>::uninit(); 16];
+    let mut wait = Some(Timeout::ZERO);
+    let mut blocked = false;
+    loop {
+        let events = queue.wait(&mut buf, wait, WaitFlags::NONE)?;
+        if events.is_empty() {
+            if ready.count_ready() > 0 || blocked {
+                return Ok(ready);
+            }
+            // Nothing was ready when subscribing, so block for the first event.
+            wait = timeout.map(Timeout::from);
+            blocked = true;
+            continue;
+        }
+        for event in events.iter() {
+            let readiness = EventFlags::from_bits_retain(event.flags) & flags;
+            for (index, _) in fds.iter().enumerate().filter(|(_, &fd)| fd == event.fd) {
+                ready.set(index, readiness);
+            }
+        }
+        wait = Some(Timeout::ZERO);
+    }
+}
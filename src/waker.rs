@@ -0,0 +1,74 @@
+//! Waking up a thread blocked on an event queue, without ever blocking the waker.
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::io::{self, pipe, ErrorKind, PipeReader, PipeWriter, Read, Write};
+use std::os::fd::AsRawFd;
+
+#[cfg(target_os = "redox")]
+use crate::wrappers::{io_error, set_nonblocking};
+
+/// A doorbell that becomes readable when woken, however many times it is woken
+///
+/// Unlike [`EventCounter`](crate::EventCounter), wakes are coalesced: only the first wake since
+/// the last [`wake_count`](Self::wake_count) writes to the underlying pipe, so at most one byte
+/// is ever buffered and waking never blocks, no matter how many wakes pile up before the owner
+/// gets to them. The number of wakes is kept in memory, so all wakers must share the same
+/// instance, typically through an `Arc`.
+#[derive(Debug)]
+pub struct EventWaker {
+    reader: PipeReader,
+    writer: PipeWriter,
+    notified: AtomicBool,
+    count: AtomicU64,
+}
+
+impl EventWaker {
+    pub fn new() -> io::Result<Self> {
+        let (reader, writer) = pipe()?;
+        // Elsewhere there is no fcntl to call, but the flag is only seen set once its byte is
+        // written or about to be, so a read can only block briefly.
+        #[cfg(target_os = "redox")]
+        set_nonblocking(reader.as_raw_fd() as usize, true).map_err(io_error)?;
+        Ok(Self {
+            reader,
+            writer,
+            notified: AtomicBool::new(false),
+            count: AtomicU64::new(0),
+        })
+    }
+    /// Make the waker readable, if it is not already
+    pub fn wake(&self) -> io::Result<()> {
+        self.count.fetch_add(1, Ordering::AcqRel);
+        if self.notified.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let res = (&self.writer).write_all(&[1]);
+        if res.is_err() {
+            // Nothing was written, so the next wake must try again.
+            self.notified.store(false, Ordering::Release);
+        }
+        res
+    }
+    /// Return the number of wakes since the last call, and rearm the waker
+    ///
+    /// This does not block. It returns zero if the waker was not woken, which can also happen
+    /// after an event for a wake that was already counted by the previous call.
+    pub fn wake_count(&self) -> io::Result<u64> {
+        if self.notified.load(Ordering::Acquire) {
+            // The waker that set the flag has written, or is about to write, exactly one byte. If
+            // it is not there yet, the flag stays set so that the next call reads it.
+            match (&self.reader).read(&mut [0]) {
+                Ok(_) => {
+                    self.notified.swap(false, Ordering::AcqRel);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(self.count.swap(0, Ordering::AcqRel))
+    }
+    /// The file descriptor to subscribe to, which is readable while a wake is pending
+    #[inline]
+    pub fn as_raw_fd(&self) -> usize {
+        self.reader.as_raw_fd() as usize
+    }
+}
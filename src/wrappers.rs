@@ -1,26 +1,151 @@
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
 use core::marker::PhantomData;
-use core::mem::MaybeUninit;
+use core::mem::{self, MaybeUninit};
+use core::panic::Location;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
+use libredox::data::TimeSpec;
+#[cfg(feature = "std")]
+use libredox::errno::EINTR;
+use libredox::errno::{EBADF, EINVAL, ENOSYS, EPROTO, ETIMEDOUT};
+#[cfg(feature = "alloc")]
+use libredox::errno::{EBUSY, EDQUOT, ENOENT};
 use libredox::error::{Error, Result};
 
 use crate::raw;
-pub use crate::raw::EventFlags;
+pub use crate::raw::EventQueueGetEventsFlagsV1 as WaitFlags;
+pub use crate::raw::{EventFlags, ParseEventFlagsError};
 
+/// The raw handle of an event queue, which may be invalid
+///
+/// Queue handles are file descriptors, so 0 is a perfectly valid handle. The invalid handles are
+/// therefore `usize::MAX` and `usize::MAX - 1`, which can never be returned by a successful
+/// syscall.
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct QueueHandle(usize);
+
+impl QueueHandle {
+    const INVALID: Self = Self(usize::MAX);
+    // The queue has been destroyed, and its fd number may since have been reused.
+    const CLOSED: Self = Self(usize::MAX - 1);
+
+    fn get(self) -> Result<usize> {
+        match self {
+            Self::INVALID => Err(Error::new(EBADF)),
+            Self::CLOSED => {
+                debug_assert!(false, "event queue used after shutdown");
+                Err(Error::new(EBADF))
+            }
+            Self(queue) => Ok(queue),
+        }
+    }
+    fn is_valid(self) -> bool {
+        self != Self::INVALID && self != Self::CLOSED
+    }
+}
+impl fmt::Debug for QueueHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::INVALID => f.write_str("<invalid>"),
+            Self::CLOSED => f.write_str("<closed>"),
+            Self(queue) => write!(f, "{}", queue),
+        }
+    }
+}
+
+/// An event queue, as a thin wrapper around its file descriptor
+///
+/// # Sharing a queue
+///
+/// The queue fd can be shared with other threads, or sent to another process and adopted there
+/// with [`from_raw_fd`](Self::from_raw_fd), for instance to have a supervisor subscribe to fds
+/// that a worker then waits on. All holders see the same subscriptions, and the user data of
+/// an event is whatever its subscriber chose.
+///
+/// When several threads or processes wait on the same queue at once, each event is returned to
+/// exactly one of them. Which one is unspecified, as is the order between waiters, so events
+/// for the same fd may be handled concurrently.
 pub struct RawEventQueue {
-    inner: usize,
+    inner: QueueHandle,
+    #[cfg(feature = "stats")]
+    stats: crate::stats::Counters,
 }
 pub type RawEvent = raw::RawEventV1;
 impl RawEventQueue {
+    #[cfg_attr(feature = "leak-check", track_caller)]
     pub fn new() -> Result<Self> {
+        #[cfg(feature = "stats")]
+        let stats = crate::stats::Counters::new();
+        #[cfg(feature = "stats")]
+        stats.create();
+
+        let queue = Error::demux(unsafe { raw::redox_event_queue_create_v1(0) })?;
+        #[cfg(feature = "leak-check")]
+        crate::leak::created(queue, core::panic::Location::caller());
+
         Ok(Self {
-            inner: Error::demux(unsafe { raw::redox_event_queue_create_v1(0) })?,
+            inner: QueueHandle(queue),
+            #[cfg(feature = "stats")]
+            stats,
         })
     }
+    /// Take ownership of an existing queue fd
+    ///
+    /// This includes fds that older code opened from the `event:` scheme directly, so such code
+    /// can be migrated incrementally. Subscriptions made by writing `syscall::Event` structs to
+    /// the fd remain in place, and with the `redox_syscall` feature, [`RawEvent`] converts to and
+    /// from `syscall::Event`. This is also how a queue received from another process is used,
+    /// see [Sharing a queue](Self#sharing-a-queue).
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open event queue, which is closed when the returned queue is dropped.
+    pub const unsafe fn from_raw_fd(fd: usize) -> Self {
+        Self {
+            inner: QueueHandle(fd),
+            #[cfg(feature = "stats")]
+            stats: crate::stats::Counters::new(),
+        }
+    }
+    /// Give up ownership of the queue fd, without closing it
+    pub fn into_raw_fd(self) -> usize {
+        let fd = self.inner.0;
+        #[cfg(feature = "leak-check")]
+        crate::leak::destroyed(fd);
+        mem::forget(self);
+        fd
+    }
+    /// Create a placeholder that does not refer to any queue
+    ///
+    /// Every operation on it fails with `EBADF`, and dropping it does nothing.
+    pub const fn dangling() -> Self {
+        Self {
+            inner: QueueHandle::INVALID,
+            #[cfg(feature = "stats")]
+            stats: crate::stats::Counters::new(),
+        }
+    }
     /// Subscribe to events produced by `fd`
     pub fn subscribe(&self, fd: usize, user_data: usize, flags: EventFlags) -> Result<()> {
+        let queue = self.inner.get()?;
+        #[cfg(feature = "stats")]
+        self.stats.ctl();
         let _ = Error::demux(unsafe {
-            raw::redox_event_queue_ctl_v1(self.inner, fd, flags.bits(), user_data)
+            raw::redox_event_queue_ctl_v1(queue, fd, flags.bits(), user_data)
         })?;
+        #[cfg(feature = "leak-check")]
+        crate::leak::subscribed(queue, fd, flags);
         Ok(())
     }
     /// Unsubscribe from events produced by `fd`
@@ -28,32 +153,270 @@ impl RawEventQueue {
         // TODO: Will user_data be needed?
         self.subscribe(fd, 0, EventFlags::empty())
     }
+    /// Block until events are available or `timeout` has passed, and read them into `buf`
+    ///
+    /// This exposes every parameter of the underlying syscall, so a single wait can both batch
+    /// and time out. Returns the events that were read, which is none if `timeout` expired
+    /// first.
+    pub fn wait<'a>(
+        &self,
+        buf: &'a mut [MaybeUninit<RawEvent>],
+        timeout: Option<Timeout>,
+        flags: WaitFlags,
+    ) -> Result<&'a mut [RawEvent]> {
+        self.wait_with_sigmask(buf, timeout, flags, None)
+    }
+    // Like `wait`, atomically replacing the signal mask of the thread with `sigmask` while
+    // blocked.
+    pub(crate) fn wait_with_sigmask<'a>(
+        &self,
+        buf: &'a mut [MaybeUninit<RawEvent>],
+        timeout: Option<Timeout>,
+        flags: WaitFlags,
+        sigmask: Option<&libredox::data::SigSet>,
+    ) -> Result<&'a mut [RawEvent]> {
+        let timeout = timeout.map(|timeout| timeout.time);
+        let queue = self.inner.get()?;
+
+        let res = Error::demux(unsafe {
+            raw::redox_event_queue_get_events_v1(
+                queue,
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                flags.bits() as u32,
+                timeout
+                    .as_ref()
+                    .map_or(core::ptr::null(), |timeout| timeout),
+                sigmask.map_or(core::ptr::null(), |sigmask| sigmask),
+            )
+        });
+        #[cfg(feature = "stats")]
+        self.stats.wait(*res.as_ref().unwrap_or(&0));
+        let count = res?;
+        if count > buf.len() {
+            return Err(invariant_violated(
+                "kernel returned more events than requested",
+            ));
+        }
+
+        // SAFETY: The kernel has initialized the first `count` events.
+        Ok(unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr().cast(), count) })
+    }
+    fn get_event(&self, timeout: Option<Timeout>) -> Result<Option<RawEvent>> {
+        let mut event = [MaybeUninit::uninit()];
+        Ok(self
+            .wait(&mut event, timeout, WaitFlags::NONE)?
+            .first()
+            .copied())
+    }
     // TODO: next_events
     pub fn next_event(&self) -> Result<RawEvent> {
-        let mut event = MaybeUninit::uninit();
-
-        unsafe {
-            let res = Error::demux(raw::redox_event_queue_get_events_v1(
-                self.inner,
-                event.as_mut_ptr(),
-                1,
-                0,
-                core::ptr::null(),
-                core::ptr::null(),
-            ))?;
-            assert_eq!(res, 1, "EOF is not yet well defined for event queues");
-            Ok(event.assume_init())
+        self.get_event(None)?
+            .ok_or_else(|| invariant_violated("EOF is not yet well defined for event queues"))
+    }
+    /// Block until the next event, or until `timeout` has passed
+    pub fn next_event_timeout(&self, timeout: impl Into<Timeout>) -> Result<Option<RawEvent>> {
+        self.get_event(Some(timeout.into()))
+    }
+    /// Block until events are available, and append up to `max` of them to `events`
+    ///
+    /// The events are read directly into the spare capacity of `events`, which is grown first if
+    /// needed. Returns the number of events appended.
+    #[cfg(feature = "alloc")]
+    pub fn read_into_vec(&self, events: &mut Vec<RawEvent>, max: usize) -> Result<usize> {
+        if max == 0 {
+            return Ok(0);
         }
+        events.reserve(max);
+
+        let count = self
+            .wait(
+                &mut events.spare_capacity_mut()[..max],
+                None,
+                WaitFlags::NONE,
+            )?
+            .len();
+        // SAFETY: The kernel has initialized the first `count` events of the spare capacity.
+        unsafe { events.set_len(events.len() + count) };
+        Ok(count)
     }
     pub fn iter(&self) -> impl Iterator<Item = Result<RawEvent>> + '_ {
         core::iter::from_fn(|| Some(self.next_event()))
     }
-    // TODO: "next_event_nonblock"?
+    /// Return the next event if one is already available, without blocking
+    pub fn try_next_event(&self) -> Result<Option<RawEvent>> {
+        self.get_event(Some(Timeout::ZERO))
+    }
+    /// Destroy the queue now, rather than when it is dropped
+    ///
+    /// Any later use of the queue fails with `EBADF`, and panics in debug builds, instead of
+    /// operating on an fd number that may have been reused since.
+    pub fn shutdown(&mut self) -> Result<()> {
+        let queue = self.inner.get()?;
+        self.inner = QueueHandle::CLOSED;
+        #[cfg(feature = "stats")]
+        self.stats.destroy();
+        #[cfg(feature = "leak-check")]
+        crate::leak::destroyed(queue);
+        Error::demux(unsafe { raw::redox_event_queue_destroy_v1(queue) })?;
+        Ok(())
+    }
+    /// The syscalls made through this queue so far
+    #[cfg(feature = "stats")]
+    pub fn syscall_stats(&self) -> crate::SyscallStats {
+        self.stats.snapshot()
+    }
+    /// The file descriptor of the queue, which becomes readable when events are pending
+    ///
+    /// This is `usize::MAX` for a [`dangling`](Self::dangling) queue, and `usize::MAX - 1` after
+    /// [`shutdown`](Self::shutdown).
+    #[inline]
+    pub fn as_raw_fd(&self) -> usize {
+        self.inner.0
+    }
+}
+/// The `(major, minor)` version of the event ABI used by this crate
+///
+/// The queue functions are versioned symbols of the C library, and this is the version of the
+/// symbols the crate calls. Whether the running kernel implements them can be checked up front
+/// with [`EventQueue::with_min_version`].
+pub const fn abi_version() -> (u32, u32) {
+    (1, 0)
+}
+
+/// How long a wait with a timeout took, as returned by [`EventQueue::next_event_timed`]
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ElapsedInfo {
+    /// The time actually spent waiting, which may exceed the timeout
+    pub elapsed: Duration,
+    /// Whether the wait ended because the timeout expired, rather than with an event
+    pub timed_out: bool,
+}
+
+/// How long to wait for events, relative to when the wait starts
+///
+/// The kernel measures timeouts with the monotonic clock; there is no flag selecting the realtime
+/// clock. Callers with a wall-clock deadline should subtract the current time themselves, keeping
+/// in mind that the wait will not follow later adjustments to the system time. Both durations and
+/// raw `TimeSpec`s convert into a timeout, so APIs taking `impl Into<Timeout>` accept either.
+#[derive(Clone, Copy)]
+pub struct Timeout {
+    time: TimeSpec,
+}
+
+impl Timeout {
+    /// Do not wait at all
+    pub const ZERO: Self = Self::from_duration(Duration::ZERO);
+
+    pub const fn new(time: TimeSpec) -> Self {
+        Self { time }
+    }
+    pub const fn from_duration(duration: Duration) -> Self {
+        let tv_sec = if duration.as_secs() > i64::MAX as u64 {
+            i64::MAX
+        } else {
+            duration.as_secs() as i64
+        };
+        Self::new(TimeSpec {
+            tv_sec: tv_sec as _,
+            tv_nsec: duration.subsec_nanos() as _,
+        })
+    }
+    #[inline]
+    pub fn time(&self) -> TimeSpec {
+        self.time
+    }
+    /// The timeout as a duration, with negative times counting as zero
+    pub fn duration(&self) -> Duration {
+        Duration::new(
+            self.time.tv_sec.max(0) as u64,
+            self.time.tv_nsec.clamp(0, 999_999_999) as u32,
+        )
+    }
+}
+impl From<Duration> for Timeout {
+    fn from(duration: Duration) -> Self {
+        Self::from_duration(duration)
+    }
+}
+impl From<TimeSpec> for Timeout {
+    fn from(time: TimeSpec) -> Self {
+        Self::new(time)
+    }
+}
+impl fmt::Debug for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timeout")
+            .field("tv_sec", &self.time.tv_sec)
+            .field("tv_nsec", &self.time.tv_nsec)
+            .finish()
+    }
+}
+// Reject user data reserved for the crate's own subscriptions.
+fn app_user_data(user_data: usize) -> Result<usize> {
+    if user_data > MAX_APP_VALUE {
+        return Err(Error::new(EINVAL));
+    }
+    Ok(user_data)
+}
+
+// Set or clear O_NONBLOCK on `fd`, returning whether it was changed.
+#[cfg(feature = "alloc")]
+pub(crate) fn set_nonblocking(fd: usize, nonblocking: bool) -> Result<bool> {
+    const F_GETFL: usize = 3;
+    const F_SETFL: usize = 4;
+    let nonblock = libredox::flag::O_NONBLOCK as usize;
+
+    let status = libredox::call::fcntl(fd, F_GETFL, 0)?;
+    if (status & nonblock != 0) == nonblocking {
+        return Ok(false);
+    }
+    libredox::call::fcntl(fd, F_SETFL, status ^ nonblock)?;
+    Ok(true)
+}
+/// A function told about a broken invariant, with where it was detected
+pub type InvariantHook = fn(what: &str, location: &'static Location<'static>);
+
+static INVARIANT_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Call `hook` for every broken invariant that is turned into an error, replacing any previous
+/// hook
+///
+/// Broken invariants, such as the kernel returning more events than asked for, panic in debug
+/// builds and with the `strict` feature. Otherwise they become `EPROTO` errors, so that a daemon
+/// can recover instead of aborting, and are reported to the hook, e.g. to be logged or counted.
+/// Nothing is reported until a hook is set.
+pub fn set_invariant_hook(hook: InvariantHook) {
+    INVARIANT_HOOK.store(hook as *mut (), Ordering::Release);
+}
+/// Report a broken invariant, as described in [`set_invariant_hook`]
+#[cold]
+#[track_caller]
+pub(crate) fn invariant_violated(what: &str) -> Error {
+    if cfg!(any(debug_assertions, feature = "strict")) {
+        panic!("{what}");
+    }
+    let hook = INVARIANT_HOOK.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // SAFETY: Only `InvariantHook`s are ever stored.
+        let hook = unsafe { mem::transmute::<*mut (), InvariantHook>(hook) };
+        hook(what, Location::caller());
+    }
+    Error::new(EPROTO)
+}
+#[cfg(feature = "std")]
+pub(crate) fn io_error(error: Error) -> std::io::Error {
+    std::io::Error::from_raw_os_error(error.errno())
 }
 impl Drop for RawEventQueue {
     fn drop(&mut self) {
-        unsafe {
-            let _ = Error::demux(raw::redox_event_queue_destroy_v1(self.inner));
+        if self.inner.is_valid() {
+            #[cfg(feature = "leak-check")]
+            crate::leak::destroyed(self.inner.0);
+            unsafe {
+                let _ = Error::demux(raw::redox_event_queue_destroy_v1(self.inner.0));
+            }
         }
     }
 }
@@ -89,13 +452,206 @@ macro_rules! user_data {
 
                 unsafe { ::core::mem::transmute(raw) }
             }
+            fn try_from_user_data(raw: usize) -> Option<Self> {
+                if raw < [$(Self::$variant),*].len() {
+                    Some(Self::from_user_data(raw))
+                } else {
+                    None
+                }
+            }
+        }
+    };
+    // With explicit values, which stay the same when variants are added or reordered. Duplicate
+    // values are rejected by the compiler, and reserved ones by the assertion.
+    {
+        $vis:vis enum $name:ident {
+            $($variant:ident = $value:expr),*$(,)?
+        }
+    } => {
+        #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+        #[repr(usize)]
+        $vis enum $name {
+            $($variant = $value),*
+        }
+
+        const _: () = {
+            $(assert!(
+                $name::$variant as usize <= $crate::MAX_APP_VALUE,
+                ::core::concat!("user data of ", ::core::stringify!($variant), " is reserved"),
+            );)*
+        };
+
+        impl $crate::UserData for $name {
+            fn into_user_data(self) -> usize {
+                self as usize
+            }
+            fn from_user_data(raw: usize) -> Self {
+                Self::try_from_user_data(raw).expect("user data is not a variant")
+            }
+            fn try_from_user_data(raw: usize) -> Option<Self> {
+                $(if raw == Self::$variant as usize {
+                    return Some(Self::$variant);
+                })*
+                None
+            }
+        }
+    };
+    // With ranges of values as well, such as `Client(100..200)`, whose variant carries the index
+    // of the value within the range. Overlapping and reserved values are rejected by the
+    // assertion.
+    {
+        $vis:vis enum $name:ident {
+            $($variant:ident $(($range:expr))? $(= $value:expr)?),*$(,)?
+        }
+    } => {
+        #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+        $vis enum $name {
+            $($variant $(($crate::__user_data_variant!(@field $range)))?),*
+        }
+
+        const _: () = {
+            // The values of each variant, as `start..end`.
+            let values = [$($crate::__user_data_variant!(@values $variant $(($range))? $(= $value)?)),*];
+            let mut i = 0;
+            while i < values.len() {
+                let (start, end) = values[i];
+                assert!(
+                    start <= $crate::MAX_APP_VALUE && end <= $crate::MAX_APP_VALUE + 1,
+                    "user data is reserved",
+                );
+                assert!(start < end, "user data range is empty");
+                let mut j = 0;
+                while j < i {
+                    assert!(
+                        end <= values[j].0 || values[j].1 <= start,
+                        "user data values overlap",
+                    );
+                    j += 1;
+                }
+                i += 1;
+            }
+        };
+
+        impl $crate::UserData for $name {
+            #[allow(irrefutable_let_patterns)]
+            fn into_user_data(self) -> usize {
+                $($crate::__user_data_variant!(@into self, $name::$variant $(($range))? $(= $value)?);)*
+                unreachable!()
+            }
+            fn from_user_data(raw: usize) -> Self {
+                Self::try_from_user_data(raw).expect("user data is not a variant")
+            }
+            fn try_from_user_data(raw: usize) -> Option<Self> {
+                $($crate::__user_data_variant!(@from raw, $name::$variant $(($range))? $(= $value)?);)*
+                None
+            }
+        }
+    };
+}
+// The parts of `user_data!` that differ between single values and ranges.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __user_data_variant {
+    (@field $range:expr) => {
+        usize
+    };
+    (@values $variant:ident = $value:expr) => {
+        ($value as usize, ($value as usize).saturating_add(1))
+    };
+    (@values $variant:ident ($range:expr)) => {
+        ($range.start as usize, $range.end as usize)
+    };
+    // Once a variant has a value or range, they all need exactly one.
+    (@values $variant:ident $($rest:tt)*) => {
+        ::core::compile_error!(::core::concat!(
+            "variant ",
+            ::core::stringify!($variant),
+            " of user_data! needs exactly one of a value or a range",
+        ))
+    };
+    (@into $self:ident, $name:ident::$variant:ident = $value:expr) => {
+        if let $name::$variant = $self {
+            return $value;
+        }
+    };
+    (@into $self:ident, $name:ident::$variant:ident ($range:expr)) => {
+        if let $name::$variant(index) = $self {
+            let range: ::core::ops::Range<usize> = $range;
+            assert!(index < range.len(), "user data index is out of range");
+            return range.start + index;
+        }
+    };
+    (@from $raw:ident, $name:ident::$variant:ident = $value:expr) => {
+        if $raw == $value {
+            return Some($name::$variant);
+        }
+    };
+    (@from $raw:ident, $name:ident::$variant:ident ($range:expr)) => {
+        let range: ::core::ops::Range<usize> = $range;
+        if range.contains(&$raw) {
+            return Some($name::$variant($raw - range.start));
         }
     };
+    // Invalid variants, reported by `@values`.
+    (@into $($rest:tt)*) => {};
+    (@from $($rest:tt)*) => {};
 }
 
+/// Pass an [`Event`] to the handler for its user data, an enum declared with [`user_data!`]
+///
+/// The handlers are the arms of a `match` on the user data, so forgetting a variant is a
+/// compile error rather than a silently dropped event:
+///
+/// ```ignore
+/// event::dispatch_events!(queue.next_event()?, Token {
+///     Listener => |event| server.accept(event.flags),
+///     Config => |_| server.reload(),
+/// });
+/// ```
+#[macro_export]
+macro_rules! dispatch_events {
+    ($event:expr, $name:ident {
+        $($variant:ident => $handler:expr),*$(,)?
+    }) => {{
+        let event: $crate::Event<$name> = $event;
+        #[deny(unreachable_patterns)]
+        match event.user_data {
+            $($name::$variant => $crate::__call_handler($handler, event),)*
+        }
+    }};
+}
+// Gives handler closures of `dispatch_events!` their argument type.
+#[doc(hidden)]
+pub fn __call_handler<U: UserData, R>(handler: impl FnOnce(Event<U>) -> R, event: Event<U>) -> R {
+    handler(event)
+}
+
+/// User data values above [`MAX_APP_VALUE`], used by the crate for its own subscriptions.
+pub(crate) mod reserved {
+    #[cfg(feature = "std")]
+    pub const INTERRUPT: usize = usize::MAX;
+    #[cfg(feature = "std")]
+    pub const WAKER: usize = usize::MAX - 1;
+}
+
+/// The largest user data value that can be subscribed with through an [`EventQueue`]
+///
+/// The values above it are reserved for subscriptions made by the crate itself, so they never
+/// collide with those of the application. Subscribing with one fails with `EINVAL`, and events
+/// carrying one are never converted to a [`UserData`] type.
+pub const MAX_APP_VALUE: usize = usize::MAX - 8;
+
 pub trait UserData: Clone + Copy {
     fn into_user_data(self) -> usize;
     fn from_user_data(user_data: usize) -> Self;
+    /// Like [`from_user_data`](Self::from_user_data), but returning `None` for values that do not
+    /// correspond to any `Self`, rather than panicking
+    ///
+    /// Queues use this to convert events, so types that can reject values should override it.
+    /// Values above [`MAX_APP_VALUE`] are rejected by default.
+    fn try_from_user_data(user_data: usize) -> Option<Self> {
+        (user_data <= MAX_APP_VALUE).then(|| Self::from_user_data(user_data))
+    }
 }
 impl UserData for usize {
     fn into_user_data(self) -> usize {
@@ -124,9 +680,157 @@ pub struct Event<U: UserData> {
     pub fd: usize,
 }
 
+/// An event for a subscription made with [`EventQueue::subscribe2`]
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug)]
+pub struct Event2 {
+    pub token: u32,
+    pub tag: u32,
+    pub flags: EventFlags,
+    pub fd: usize,
+}
+
+#[cfg(feature = "alloc")]
+type EventFilter = Box<dyn Fn(&RawEvent) -> bool>;
+
+/// The state of a single subscription, as last requested through an [`EventQueue`]
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug)]
+struct Subscription {
+    user_data: usize,
+    flags: EventFlags,
+}
+
+/// A subscription removed from a queue by [`EventQueue::detach`], for recreating it elsewhere
+///
+/// This is typically used when the subscribed fd is sent to another process, where the
+/// subscription can be restored with [`EventQueue::attach`] after transferring
+/// [`to_bytes`](Self::to_bytes).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DetachedSubscription {
+    pub user_data: usize,
+    pub flags: EventFlags,
+}
+
+impl DetachedSubscription {
+    /// The size of the serialized representation
+    pub const SIZE: usize = mem::size_of::<usize>() + mem::size_of::<u32>();
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        let (user_data, flags) = bytes.split_at_mut(mem::size_of::<usize>());
+        user_data.copy_from_slice(&self.user_data.to_le_bytes());
+        flags.copy_from_slice(&self.flags.bits().to_le_bytes());
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        let (user_data, flags) = bytes.split_at(mem::size_of::<usize>());
+        Self {
+            user_data: usize::from_le_bytes(user_data.try_into().unwrap()),
+            flags: EventFlags::from_bits_retain(u32::from_le_bytes(flags.try_into().unwrap())),
+        }
+    }
+}
+
+/// Events read from the kernel by a single syscall, not yet returned
+struct Batch {
+    events: [MaybeUninit<RawEvent>; Batch::CAPACITY],
+    // The events in `head..end` are initialized.
+    head: usize,
+    end: usize,
+}
+
+impl Batch {
+    const CAPACITY: usize = 16;
+
+    const fn new() -> Self {
+        Self {
+            events: [MaybeUninit::uninit(); Self::CAPACITY],
+            head: 0,
+            end: 0,
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.head == self.end
+    }
+    fn as_slice(&self) -> &[RawEvent] {
+        // SAFETY: The events in `head..end` are initialized.
+        unsafe {
+            core::slice::from_raw_parts(
+                self.events[self.head..].as_ptr().cast(),
+                self.end - self.head,
+            )
+        }
+    }
+    fn pop(&mut self) -> Option<RawEvent> {
+        let raw = *self.as_slice().first()?;
+        self.head += 1;
+        Some(raw)
+    }
+    #[cfg(feature = "alloc")]
+    fn retain(&mut self, mut f: impl FnMut(&RawEvent) -> bool) {
+        let mut end = self.head;
+        for i in self.head..self.end {
+            // SAFETY: The events in `head..end` are initialized.
+            let raw = unsafe { self.events[i].assume_init() };
+            if f(&raw) {
+                self.events[end].write(raw);
+                end += 1;
+            }
+        }
+        self.end = end;
+    }
+}
+
 pub struct EventQueue<U: UserData> {
     inner: RawEventQueue,
 
+    batch: RefCell<Batch>,
+    batch_size: usize,
+    // How long to poll for events before blocking.
+    #[cfg(feature = "std")]
+    spin: Duration,
+
+    // An event read by `check`, not yet returned to the caller.
+    pending: Cell<Option<RawEvent>>,
+    // Events read ahead by `unsubscribe_sync`, which come after `batch`.
+    #[cfg(feature = "alloc")]
+    stashed: RefCell<VecDeque<RawEvent>>,
+    // The rest of an event for an fd with several interests, which comes before everything else.
+    #[cfg(feature = "alloc")]
+    split: RefCell<VecDeque<RawEvent>>,
+
+    #[cfg(feature = "alloc")]
+    subscriptions: RefCell<BTreeMap<usize, Subscription>>,
+    #[cfg(feature = "alloc")]
+    max_subscriptions: Option<usize>,
+    // The number of events returned for each subscribed fd, if counting is enabled.
+    #[cfg(feature = "alloc")]
+    fire_counts: Option<RefCell<BTreeMap<usize, u64>>>,
+    #[cfg(feature = "alloc")]
+    pending_ctl: Option<alloc::sync::Arc<crate::PendingCtl<U>>>,
+    // Fds switched to nonblocking mode by subscribe_nonblocking, to be restored on unsubscribe.
+    #[cfg(feature = "alloc")]
+    nonblocking: RefCell<BTreeSet<usize>>,
+    // The user data and flags of each interest in fds subscribed with subscribe_interest.
+    #[cfg(feature = "alloc")]
+    interests: RefCell<BTreeMap<usize, Vec<(usize, EventFlags)>>>,
+
+    #[cfg(feature = "alloc")]
+    filter: Option<EventFilter>,
+    #[cfg(feature = "alloc")]
+    filtered: Cell<u64>,
+
+    #[cfg(feature = "std")]
+    history: Option<RefCell<crate::history::History>>,
+    #[cfg(feature = "std")]
+    interrupter: core::cell::OnceCell<crate::interrupt::Interrupter>,
+
+    // Without room to pack both words into user_data, subscribe2 keys them by fd instead.
+    #[cfg(all(feature = "alloc", not(target_pointer_width = "64")))]
+    tags: RefCell<BTreeMap<usize, (u32, u32)>>,
+
     // We'll be casting user_data to and from U, so ensure it's invariant.
     _marker: PhantomData<*mut U>,
 }
@@ -134,34 +838,1157 @@ pub struct EventQueue<U: UserData> {
 impl<U: UserData> EventQueue<U> {
     /// Create a new event queue
     #[inline]
+    #[cfg_attr(feature = "leak-check", track_caller)]
     pub fn new() -> Result<Self> {
         Ok(EventQueue {
             inner: RawEventQueue::new()?,
+            batch: RefCell::new(Batch::new()),
+            batch_size: Batch::CAPACITY,
+            #[cfg(feature = "std")]
+            spin: Duration::ZERO,
+            pending: Cell::new(None),
+            #[cfg(feature = "alloc")]
+            stashed: RefCell::new(VecDeque::new()),
+            #[cfg(feature = "alloc")]
+            split: RefCell::new(VecDeque::new()),
+            #[cfg(feature = "alloc")]
+            subscriptions: RefCell::new(BTreeMap::new()),
+            #[cfg(feature = "alloc")]
+            max_subscriptions: None,
+            #[cfg(feature = "alloc")]
+            fire_counts: None,
+            #[cfg(feature = "alloc")]
+            pending_ctl: None,
+            #[cfg(feature = "alloc")]
+            nonblocking: RefCell::new(BTreeSet::new()),
+            #[cfg(feature = "alloc")]
+            interests: RefCell::new(BTreeMap::new()),
+            #[cfg(feature = "alloc")]
+            filter: None,
+            #[cfg(feature = "alloc")]
+            filtered: Cell::new(0),
+            #[cfg(feature = "std")]
+            history: None,
+            #[cfg(feature = "std")]
+            interrupter: core::cell::OnceCell::new(),
+            #[cfg(all(feature = "alloc", not(target_pointer_width = "64")))]
+            tags: RefCell::new(BTreeMap::new()),
             _marker: PhantomData,
         })
     }
+    /// Create a new event queue that remembers the last `capacity` events it delivered
+    ///
+    /// The events can be retrieved with [`history`](Self::history), and are written to stderr if
+    /// the queue is dropped while panicking, so crash reports show what led up to the failure.
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn with_history(capacity: usize) -> Result<Self> {
+        let mut queue = Self::new()?;
+        queue.history = Some(RefCell::new(crate::history::History::new(capacity)));
+        Ok(queue)
+    }
+    /// The last events delivered, oldest first, if the queue was created
+    /// [`with_history`](Self::with_history)
+    #[cfg(feature = "std")]
+    pub fn history(&self) -> Vec<crate::HistoryEntry> {
+        self.history
+            .as_ref()
+            .map_or_else(Vec::new, |history| history.borrow().entries())
+    }
+    /// Create a new event queue, failing with `ENOSYS` unless the event ABI is at least
+    /// `version`, given as `(major, minor)`
+    ///
+    /// The kernel has no call reporting its event ABI version, so this probes the calls the
+    /// queue relies on instead: creating the queue, and a wait that returns immediately. Either
+    /// failing with `ENOSYS` means the ABI is missing, and is reported here rather than by the
+    /// first wait. Versions newer than [`abi_version`] always fail, since this crate cannot make
+    /// use of them even when the kernel provides them.
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn with_min_version(version: (u32, u32)) -> Result<Self> {
+        if abi_version() < version {
+            return Err(Error::new(ENOSYS));
+        }
+        let queue = Self::new()?;
+        let mut buf = [MaybeUninit::uninit()];
+        match queue
+            .inner
+            .wait(&mut buf, Some(Timeout::ZERO), WaitFlags::empty())
+        {
+            Err(err) if err.errno() == ENOSYS => Err(err),
+            _ => Ok(queue),
+        }
+    }
+    /// Subscribe to events produced by `fd`, replacing any previous subscription or interests
     #[inline]
     pub fn subscribe(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
-        self.inner.subscribe(fd, data.into_user_data(), flags)
+        self.subscribe_raw(fd, data.into_user_data(), flags)
     }
+    /// Unsubscribe from events produced by `fd`, removing all of its interests
     #[inline]
     pub fn unsubscribe(&self, fd: usize) -> Result<()> {
-        self.inner.unsubscribe(fd)
+        self.subscribe_raw(fd, 0, EventFlags::empty())
+    }
+    /// Add an interest in events of `fd`, alongside the others subscribed this way
+    ///
+    /// Unlike [`subscribe`](Self::subscribe), this allows several components to subscribe to the
+    /// same fd, for example one handling reads and another writes. The fd is subscribed to the
+    /// union of all its interests, and each event is split into one event per interest it
+    /// matches, carrying that interest's `data` and only the flags it asked for. Adding an
+    /// interest with the same `data` again replaces its flags.
+    #[cfg(feature = "alloc")]
+    pub fn subscribe_interest(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        let user_data = app_user_data(data.into_user_data())?;
+        let mut list = self
+            .interests
+            .borrow()
+            .get(&fd)
+            .cloned()
+            .unwrap_or_default();
+        match list.iter_mut().find(|(other, _)| *other == user_data) {
+            Some((_, interest)) => *interest = flags,
+            None => list.push((user_data, flags)),
+        }
+        self.set_interests(fd, list)
+    }
+    /// Remove the interest in events of `fd` that was added with `data`
+    ///
+    /// The fd is unsubscribed once it has no interests left. Fails with `ENOENT` if there is no
+    /// such interest.
+    #[cfg(feature = "alloc")]
+    pub fn unsubscribe_interest(&self, fd: usize, data: U) -> Result<()> {
+        let user_data = data.into_user_data();
+        let mut list = self
+            .interests
+            .borrow()
+            .get(&fd)
+            .cloned()
+            .unwrap_or_default();
+        let len = list.len();
+        list.retain(|(other, _)| *other != user_data);
+        if list.len() == len {
+            return Err(Error::new(ENOENT));
+        }
+        self.set_interests(fd, list)
+    }
+    #[cfg(feature = "alloc")]
+    fn set_interests(&self, fd: usize, list: Vec<(usize, EventFlags)>) -> Result<()> {
+        let flags = list
+            .iter()
+            .fold(EventFlags::empty(), |all, (_, flags)| all | *flags);
+        // Events carry the fd, so it is all the kernel needs to know.
+        self.subscribe_raw(fd, fd, flags)?;
+        // Room for splitting an event, so that `demux` does not allocate while dispatching.
+        self.split.borrow_mut().reserve(list.len());
+
+        let mut interests = self.interests.borrow_mut();
+        if list.is_empty() {
+            interests.remove(&fd);
+        } else {
+            interests.insert(fd, list);
+        }
+        Ok(())
+    }
+    pub(crate) fn subscribe_raw(
+        &self,
+        fd: usize,
+        user_data: usize,
+        flags: EventFlags,
+    ) -> Result<()> {
+        if !flags.is_empty() {
+            app_user_data(user_data)?;
+        }
+        #[cfg(feature = "alloc")]
+        if let Some(max) = self.max_subscriptions {
+            let subscriptions = self.subscriptions.borrow();
+            if !flags.is_empty() && !subscriptions.contains_key(&fd) && subscriptions.len() >= max {
+                return Err(Error::new(EDQUOT));
+            }
+        }
+        self.inner.subscribe(fd, user_data, flags)?;
+
+        #[cfg(feature = "alloc")]
+        {
+            // Whatever replaced the subscription, its interests no longer describe it;
+            // `set_interests` records them again after calling this.
+            self.interests.borrow_mut().remove(&fd);
+            let mut subscriptions = self.subscriptions.borrow_mut();
+            let added = !flags.is_empty() && !subscriptions.contains_key(&fd);
+            if let Some(ref counts) = self.fire_counts {
+                if flags.is_empty() {
+                    counts.borrow_mut().remove(&fd);
+                } else if added {
+                    counts.borrow_mut().insert(fd, 0);
+                }
+            }
+            if flags.is_empty() {
+                subscriptions.remove(&fd);
+                #[cfg(not(target_pointer_width = "64"))]
+                self.tags.borrow_mut().remove(&fd);
+                if self.nonblocking.borrow_mut().remove(&fd) {
+                    // The fd may already have been closed, which is fine.
+                    let _ = set_nonblocking(fd, false);
+                }
+            } else {
+                subscriptions.insert(fd, Subscription { user_data, flags });
+            }
+        }
+        Ok(())
+    }
+    /// Read every event the kernel has queued for this queue, returning how many were kept
+    ///
+    /// Kernel-backed fds such as pipes post their events before the operation generating them
+    /// returns, and schemes do when they post events before replying. Such events from
+    /// operations issued before the barrier are held by the queue once it returns, after those
+    /// it already held, so [`prepare`](Self::prepare) reports them and `try_next_event` returns
+    /// them without a syscall. Events a scheme posts after replying may arrive later, which no
+    /// barrier can wait for.
+    #[cfg(feature = "alloc")]
+    pub fn barrier(&self) -> Result<usize> {
+        let mut buf = [MaybeUninit::uninit(); Batch::CAPACITY];
+        let mut stashed = self.stashed.borrow_mut();
+        let mut count = 0;
+        loop {
+            let events = self
+                .inner
+                .wait(&mut buf, Some(Timeout::ZERO), WaitFlags::NONE)?;
+            if events.is_empty() {
+                return Ok(count);
+            }
+            for raw in events {
+                if self.accept(raw) {
+                    stashed.push_back(*raw);
+                    count += 1;
+                }
+            }
+        }
+    }
+    /// Subscribe to events produced by `fd`, first switching it to nonblocking mode
+    ///
+    /// If `fd` was blocking, it is switched back when unsubscribed through this queue, so it can
+    /// be handed back to code expecting blocking reads.
+    #[cfg(feature = "alloc")]
+    pub fn subscribe_nonblocking(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        let switched = set_nonblocking(fd, true)?;
+        if let Err(err) = self.subscribe(fd, data, flags) {
+            if switched {
+                let _ = set_nonblocking(fd, false);
+            }
+            return Err(err);
+        }
+        if switched {
+            self.nonblocking.borrow_mut().insert(fd);
+        }
+        Ok(())
+    }
+    /// Unsubscribe from events produced by `fd`, and discard those already queued
+    ///
+    /// Once this returns, no event for `fd` from before the call will be returned by this queue,
+    /// so state associated with `fd` can be freed right away. Events for other fds that were
+    /// queued in the meantime are kept, in order.
+    #[cfg(feature = "alloc")]
+    pub fn unsubscribe_sync(&self, fd: usize) -> Result<()> {
+        self.unsubscribe(fd)?;
+
+        if self.pending.get().is_some_and(|raw| raw.fd == fd) {
+            self.pending.set(None);
+        }
+        self.batch.borrow_mut().retain(|raw| raw.fd != fd);
+        self.split.borrow_mut().retain(|raw| raw.fd != fd);
+        let mut stashed = self.stashed.borrow_mut();
+        stashed.retain(|raw| raw.fd != fd);
+        while let Some(raw) = self.inner.get_event(Some(Timeout::ZERO))? {
+            if raw.fd != fd && self.accept(&raw) {
+                stashed.push_back(raw);
+            }
+        }
+        Ok(())
+    }
+    /// The user data and flags `fd` is currently subscribed with, if it is subscribed
+    ///
+    /// This lets components sharing a queue check for an existing subscription before replacing
+    /// it. For an fd with several [interests](Self::subscribe_interest), this is the user data of
+    /// the first one and the flags of all of them. The state is tracked by the queue rather than
+    /// queried from the kernel, so subscriptions made through [`raw`](Self::raw) are not seen.
+    /// Fails with `EINVAL` if the user data is not a valid `U`, as it may be after
+    /// [`attach`](Self::attach).
+    #[cfg(feature = "alloc")]
+    pub fn subscription(&self, fd: usize) -> Result<Option<(U, EventFlags)>> {
+        let Some(sub) = self.subscriptions.borrow().get(&fd).copied() else {
+            return Ok(None);
+        };
+        let user_data = match self.interests.borrow().get(&fd) {
+            Some(list) => list
+                .first()
+                .map_or(sub.user_data, |(user_data, _)| *user_data),
+            None => sub.user_data,
+        };
+        let data = U::try_from_user_data(user_data).ok_or(Error::new(EINVAL))?;
+        Ok(Some((data, sub.flags)))
+    }
+    /// Subscribe `fd`, or change its existing subscription, returning whether it was added
+    ///
+    /// The kernel call is add-or-modify already, so calling this again with the same arguments,
+    /// for example when retrying after reconnecting, is harmless; it then skips the syscall
+    /// entirely. As with [`subscribe`](Self::subscribe), any interests of `fd` are replaced.
+    #[cfg(feature = "alloc")]
+    pub fn upsert_subscription(&self, fd: usize, data: U, flags: EventFlags) -> Result<bool> {
+        let user_data = data.into_user_data();
+        let previous = self.subscriptions.borrow().get(&fd).copied();
+        let has_interests = self.interests.borrow().contains_key(&fd);
+        match previous {
+            Some(sub) if sub.user_data == user_data && sub.flags == flags && !has_interests => {
+                Ok(false)
+            }
+            _ => {
+                self.subscribe(fd, data, flags)?;
+                Ok(previous.is_none() && !flags.is_empty())
+            }
+        }
+    }
+    /// Remove the subscription of `fd`, returning a description of it
+    ///
+    /// Fails with `ENOENT` if `fd` is not subscribed, and with `EBUSY` if it is subscribed through
+    /// [interests](Self::subscribe_interest), which a detached subscription cannot describe.
+    #[cfg(feature = "alloc")]
+    pub fn detach(&self, fd: usize) -> Result<DetachedSubscription> {
+        if self.interests.borrow().contains_key(&fd) {
+            return Err(Error::new(EBUSY));
+        }
+        let sub = self
+            .subscriptions
+            .borrow()
+            .get(&fd)
+            .copied()
+            .ok_or(Error::new(ENOENT))?;
+        self.unsubscribe(fd)?;
+
+        Ok(DetachedSubscription {
+            user_data: sub.user_data,
+            flags: sub.flags,
+        })
+    }
+    /// Subscribe to `fd` as described by a subscription detached from this or another queue
+    ///
+    /// As with [`subscribe`](Self::subscribe), any interests of `fd` are replaced.
+    pub fn attach(&self, fd: usize, sub: DetachedSubscription) -> Result<()> {
+        self.subscribe_raw(fd, sub.user_data, sub.flags)
+    }
+    // Make the kernel report `flags` for `fd` instead of its subscription, leaving the
+    // subscription table, interests and nonblocking mode untouched until `restore`.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn retarget(&self, fd: usize, flags: EventFlags) -> Result<()> {
+        let user_data = self
+            .subscriptions
+            .borrow()
+            .get(&fd)
+            .map_or(0, |sub| sub.user_data);
+        self.inner.subscribe(fd, user_data, flags)
+    }
+    // Undo `retarget`, resubscribing `fd` as the subscription table describes it.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn restore(&self, fd: usize) -> Result<()> {
+        let sub = self.subscriptions.borrow().get(&fd).copied();
+        match sub {
+            Some(sub) => self.inner.subscribe(fd, sub.user_data, sub.flags),
+            None => self.inner.subscribe(fd, 0, EventFlags::empty()),
+        }
+    }
+    /// Choose the user data and interest of a subscription, to be made once its fd exists
+    ///
+    /// This lets a connection have its token and state before its socket is open. Fails with
+    /// `EINVAL` if `data` is [reserved](MAX_APP_VALUE), like subscribing would.
+    pub fn reserve(&self, data: U, flags: EventFlags) -> Result<PendingRegistration<'_, U>> {
+        app_user_data(data.into_user_data())?;
+        Ok(PendingRegistration {
+            queue: self,
+            data,
+            flags,
+        })
+    }
+    /// Create an empty group of subscriptions on this queue, which can be paused as a whole
+    #[cfg(feature = "alloc")]
+    pub fn group(&self) -> Group<'_, U> {
+        Group {
+            queue: self,
+            members: RefCell::new(BTreeSet::new()),
+            paused: Cell::new(false),
+        }
+    }
+    /// Destroy the queue now, discarding any events that were read but not yet returned
+    ///
+    /// See [`RawEventQueue::shutdown`].
+    pub fn shutdown(&mut self) -> Result<()> {
+        let res = self.inner.shutdown();
+
+        self.pending.set(None);
+        *self.batch.get_mut() = Batch::new();
+        #[cfg(feature = "alloc")]
+        {
+            self.stashed.get_mut().clear();
+            self.split.get_mut().clear();
+            self.interests.get_mut().clear();
+            self.subscriptions.get_mut().clear();
+            if let Some(ref mut counts) = self.fire_counts {
+                counts.get_mut().clear();
+            }
+            #[cfg(not(target_pointer_width = "64"))]
+            self.tags.get_mut().clear();
+        }
+        res
+    }
+    /// Start a set of subscription changes that are applied together by
+    /// [`Transaction::commit`]
+    #[cfg(feature = "alloc")]
+    pub fn transaction(&self) -> Transaction<'_, U> {
+        Transaction {
+            queue: self,
+            ops: Vec::new(),
+        }
+    }
+    /// The syscalls made through this queue so far
+    ///
+    /// Comparing [`SyscallStats::events_per_wait`](crate::SyscallStats::events_per_wait) before
+    /// and after [`set_batch_size`](Self::set_batch_size) shows what batching saves.
+    #[cfg(feature = "stats")]
+    pub fn syscall_stats(&self) -> crate::SyscallStats {
+        self.inner.syscall_stats()
     }
     #[inline]
     pub fn raw(&self) -> &RawEventQueue {
         &self.inner
     }
-    pub fn next_event(&self) -> Result<Event<U>> {
-        self.inner.next_event().map(|raw| Event {
-            user_data: U::from_user_data(raw.user_data),
+    /// Set how many events are read from the kernel at once, up to 16
+    ///
+    /// Events are returned one at a time regardless, so larger batches only save syscalls. A
+    /// batch size of 1 makes every event be read when it is returned, as with a raw queue.
+    pub fn set_batch_size(&mut self, size: usize) {
+        self.batch_size = size.clamp(1, Batch::CAPACITY);
+    }
+    /// Limit the number of fds subscribed through this queue at once, or lift the limit with
+    /// `None`
+    ///
+    /// Subscribing another fd past the limit fails with `EDQUOT`, which the kernel does not
+    /// return for subscriptions, before anything is subscribed. Changing or removing existing
+    /// subscriptions is always allowed, and lowering the limit does not remove any. Subscriptions
+    /// made through [`raw`](Self::raw) are not counted.
+    #[cfg(feature = "alloc")]
+    pub fn set_max_subscriptions(&mut self, max: Option<usize>) {
+        self.max_subscriptions = max;
+    }
+    /// Apply the requests buffered in `ctl` whenever the next event is read, replacing any
+    /// previous buffer
+    ///
+    /// A request that fails makes the call reading the event fail. See
+    /// [`PendingCtl`](crate::PendingCtl).
+    #[cfg(feature = "alloc")]
+    pub fn set_pending_ctl(&mut self, ctl: alloc::sync::Arc<crate::PendingCtl<U>>) {
+        self.pending_ctl = Some(ctl);
+    }
+    /// Poll for events for up to `spin` before blocking, or never with `Duration::ZERO`, the
+    /// default
+    ///
+    /// Polling avoids the latency of being woken up when events arrive within `spin`, at the
+    /// cost of burning CPU time when they do not. Waits with a shorter timeout poll for their
+    /// whole timeout instead.
+    #[cfg(feature = "std")]
+    pub fn set_spin_before_block(&mut self, spin: Duration) {
+        self.spin = spin;
+    }
+    /// Only yield events for which `filter` returns true, replacing any previous filter
+    ///
+    /// Events rejected by the filter are dropped before being converted or returned, and are
+    /// counted in [`filtered_count`](Self::filtered_count). Events with user data above
+    /// [`MAX_APP_VALUE`], such as those waking a [`Poller`](crate::polling::Poller), are never filtered.
+    #[cfg(feature = "alloc")]
+    pub fn set_filter(&mut self, filter: impl Fn(&RawEvent) -> bool + 'static) {
+        self.filter = Some(Box::new(filter));
+    }
+    /// Remove the filter set by [`set_filter`](Self::set_filter), if any
+    #[cfg(feature = "alloc")]
+    pub fn clear_filter(&mut self) {
+        self.filter = None;
+    }
+    /// Start counting the events returned for each subscription, which is off by default
+    ///
+    /// Counts start at zero for the fds already subscribed, and when an fd is subscribed again
+    /// after being unsubscribed. Changing the interest of a subscription keeps its count.
+    #[cfg(feature = "alloc")]
+    pub fn enable_fire_counts(&mut self) {
+        if self.fire_counts.is_none() {
+            let counts = self.subscriptions.get_mut().keys().map(|&fd| (fd, 0));
+            self.fire_counts = Some(RefCell::new(counts.collect()));
+        }
+    }
+    /// Stop counting events, discarding the counts so far
+    #[cfg(feature = "alloc")]
+    pub fn disable_fire_counts(&mut self) {
+        self.fire_counts = None;
+    }
+    /// The number of events returned for `fd` since it was subscribed, or counting was enabled
+    ///
+    /// Returns `None` if counting is disabled, or `fd` is not subscribed through this queue. A
+    /// count that stays at zero points at a peer that has gone quiet, or at a missed event.
+    #[cfg(feature = "alloc")]
+    pub fn fire_count(&self, fd: usize) -> Option<u64> {
+        self.fire_counts.as_ref()?.borrow().get(&fd).copied()
+    }
+    /// Number of events that have been dropped by the filter
+    #[cfg(feature = "alloc")]
+    pub fn filtered_count(&self) -> u64 {
+        self.filtered.get()
+    }
+    fn accept(&self, raw: &RawEvent) -> bool {
+        #[cfg(feature = "alloc")]
+        if let Some(ref filter) = self.filter {
+            // The crate's own subscriptions are handled whatever the application filters out.
+            if raw.user_data <= MAX_APP_VALUE && !filter(raw) {
+                self.filtered.set(self.filtered.get() + 1);
+                return false;
+            }
+        }
+        let _ = raw;
+        true
+    }
+    pub(crate) fn next_raw_event_timeout(
+        &self,
+        timeout: Option<Timeout>,
+    ) -> Result<Option<RawEvent>> {
+        // Applying requests frees them and may grow the subscription table, so it is done before
+        // entering the allocation-free path.
+        #[cfg(feature = "alloc")]
+        if let Some(ref ctl) = self.pending_ctl {
+            if !ctl.is_empty() {
+                ctl.apply(self)?;
+            }
+        }
+        #[cfg(feature = "debug-rt")]
+        let _hot_path = crate::rt::HotPath::enter();
+
+        // Events read by `check` were already recorded then.
+        if let Some(raw) = self.pending.take() {
+            return Ok(Some(raw));
+        }
+        let raw = self.read_raw_event(timeout)?;
+
+        #[cfg(feature = "std")]
+        if let (Some(raw), Some(history)) = (raw, &self.history) {
+            history.borrow_mut().record(raw);
+        }
+        #[cfg(feature = "alloc")]
+        if let (Some(raw), Some(counts)) = (raw, &self.fire_counts) {
+            if let Some(count) = counts.borrow_mut().get_mut(&raw.fd) {
+                *count += 1;
+            }
+        }
+        Ok(raw)
+    }
+    /// A handle that makes the current or next wait of this queue fail with `EINTR`
+    ///
+    /// The handle can be used from a signal handler, for example to return to the main loop on
+    /// Ctrl-C even when the signal arrives right before the loop starts waiting. The first call
+    /// sets up a pipe subscribed in this queue, whose write end the handle keeps open.
+    #[cfg(feature = "std")]
+    pub fn interrupt_handle(&self) -> Result<crate::InterruptHandle> {
+        if let Some(interrupter) = self.interrupter.get() {
+            return Ok(interrupter.handle());
+        }
+        let interrupter = crate::interrupt::Interrupter::new()?;
+        // Subscribed behind the table's back, so it is never converted to U.
+        self.inner
+            .subscribe(interrupter.fd(), reserved::INTERRUPT, EventFlags::READ)?;
+        Ok(self.interrupter.get_or_init(|| interrupter).handle())
+    }
+    // Fail with EINTR if `raw` is for the interrupt pipe.
+    fn check_interrupt(&self, raw: &RawEvent) -> Result<()> {
+        #[cfg(feature = "std")]
+        if let Some(interrupter) = self.interrupter.get() {
+            if raw.user_data == reserved::INTERRUPT && raw.fd == interrupter.fd() {
+                interrupter.clear();
+                return Err(Error::new(EINTR));
+            }
+        }
+        let _ = raw;
+        Ok(())
+    }
+    fn read_raw_event(&self, timeout: Option<Timeout>) -> Result<Option<RawEvent>> {
+        #[cfg(feature = "alloc")]
+        if let Some(raw) = self.split.borrow_mut().pop_front() {
+            return Ok(Some(raw));
+        }
+        loop {
+            let raw = self.batch.borrow_mut().pop();
+            if let Some(raw) = raw {
+                self.check_interrupt(&raw)?;
+                if let Some(raw) = self.accept(&raw).then(|| self.demux(raw)).flatten() {
+                    return Ok(Some(raw));
+                }
+                continue;
+            }
+            #[cfg(feature = "alloc")]
+            if let Some(raw) = self.stashed.borrow_mut().pop_front() {
+                self.check_interrupt(&raw)?;
+                if let Some(raw) = self.demux(raw) {
+                    return Ok(Some(raw));
+                }
+                continue;
+            }
+            if !self.fill_batch(timeout)? {
+                return Ok(None);
+            }
+        }
+    }
+    // Split an event for an fd with several interests, returning the first part.
+    fn demux(&self, raw: RawEvent) -> Option<RawEvent> {
+        #[cfg(feature = "alloc")]
+        if let Some(list) = self.interests.borrow().get(&raw.fd) {
+            let flags = EventFlags::from_bits_retain(raw.flags);
+            let mut split = self.split.borrow_mut();
+            for &(user_data, interest) in list {
+                if flags.intersects(interest) {
+                    split.push_back(RawEvent {
+                        fd: raw.fd,
+                        user_data,
+                        flags: (flags & interest).bits(),
+                    });
+                }
+            }
+            return split.pop_front();
+        }
+        Some(raw)
+    }
+    // Returns false if the timeout expired before any event arrived.
+    fn fill_batch(&self, timeout: Option<Timeout>) -> Result<bool> {
+        #[cfg(feature = "std")]
+        let timeout = match self.spin(timeout)? {
+            Some(timeout) => timeout,
+            None => return Ok(true),
+        };
+        self.read_batch(timeout)
+    }
+    // Poll for events for up to the spin duration, filling the batch. Returns the timeout left
+    // for blocking, or None if events were read.
+    #[cfg(feature = "std")]
+    fn spin(&self, timeout: Option<Timeout>) -> Result<Option<Option<Timeout>>> {
+        let limit = timeout.map_or(self.spin, |timeout| self.spin.min(timeout.duration()));
+        if limit.is_zero() {
+            return Ok(Some(timeout));
+        }
+        let start = Instant::now();
+        loop {
+            if self.read_batch(Some(Timeout::ZERO))? {
+                return Ok(None);
+            }
+            if start.elapsed() >= limit {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        Ok(Some(timeout.map(|timeout| {
+            let left = timeout.duration().saturating_sub(start.elapsed());
+            Timeout::from_duration(left)
+        })))
+    }
+    fn read_batch(&self, timeout: Option<Timeout>) -> Result<bool> {
+        let mut batch = self.batch.borrow_mut();
+        let count = self
+            .inner
+            .wait(
+                &mut batch.events[..self.batch_size],
+                timeout,
+                WaitFlags::NONE,
+            )?
+            .len();
+        batch.head = 0;
+        batch.end = count;
+        Ok(count > 0)
+    }
+    pub(crate) fn next_raw_event(&self) -> Result<RawEvent> {
+        self.next_raw_event_timeout(None)?
+            .ok_or_else(|| invariant_violated("EOF is not yet well defined for event queues"))
+    }
+    pub(crate) fn try_next_raw_event(&self) -> Result<Option<RawEvent>> {
+        self.next_raw_event_timeout(Some(Timeout::ZERO))
+    }
+    // Fails with EINVAL if the user data is not a valid U, e.g. after `attach` or a subscription
+    // made through `raw`.
+    pub(crate) fn event_from_raw(raw: RawEvent) -> Result<Event<U>> {
+        let user_data = U::try_from_user_data(raw.user_data).ok_or(Error::new(EINVAL))?;
+        Ok(Event {
+            user_data,
             fd: raw.fd,
             flags: EventFlags::from_bits_retain(raw.flags),
         })
     }
+    pub fn next_event(&self) -> Result<Event<U>> {
+        Self::event_from_raw(self.next_raw_event()?)
+    }
+    /// Block until the next event, or until `timeout` has passed
+    ///
+    /// The timeout restarts whenever an event is dropped by the filter.
+    pub fn next_event_timeout(&self, timeout: impl Into<Timeout>) -> Result<Option<Event<U>>> {
+        self.next_raw_event_timeout(Some(timeout.into()))?
+            .map(Self::event_from_raw)
+            .transpose()
+    }
+    /// Like [`next_event_timeout`](Self::next_event_timeout), but also telling how long the call
+    /// actually waited
+    #[cfg(feature = "std")]
+    pub fn next_event_timed(
+        &self,
+        timeout: impl Into<Timeout>,
+    ) -> Result<(Option<Event<U>>, ElapsedInfo)> {
+        let start = Instant::now();
+        let event = self.next_event_timeout(timeout)?;
+        let info = ElapsedInfo {
+            elapsed: start.elapsed(),
+            timed_out: event.is_none(),
+        };
+        Ok((event, info))
+    }
+    /// Like [`next_event_timeout`](Self::next_event_timeout), but failing with `ETIMEDOUT`
+    /// instead of returning `None`, for code that treats a timeout as an error
+    pub fn next_event_or_timeout(&self, timeout: impl Into<Timeout>) -> Result<Event<U>> {
+        self.next_event_timeout(timeout)?
+            .ok_or(Error::new(ETIMEDOUT))
+    }
+    /// Iterate over the events arriving within the next `duration`
+    ///
+    /// The iterator ends once `duration` has passed, which makes it convenient to collect events
+    /// in batches.
+    #[cfg(feature = "std")]
+    pub fn events_for(&self, duration: Duration) -> impl Iterator<Item = Result<Event<U>>> + '_ {
+        let start = Instant::now();
+
+        core::iter::from_fn(move || {
+            let remaining = duration.checked_sub(start.elapsed())?;
+            if remaining.is_zero() {
+                return None;
+            }
+            self.next_event_timeout(remaining).transpose()
+        })
+    }
+    /// Return the next event if one is already available, without blocking
+    pub fn try_next_event(&self) -> Result<Option<Event<U>>> {
+        self.try_next_raw_event()?
+            .map(Self::event_from_raw)
+            .transpose()
+    }
+    /// The file descriptor of the queue, for embedding it in another main loop
+    #[inline]
+    pub fn as_raw_fd(&self) -> usize {
+        self.inner.as_raw_fd()
+    }
+    /// First phase of a foreign main loop iteration: return whether events are ready to dispatch
+    /// without waiting
+    ///
+    /// When this returns false, the foreign loop should wait for [`as_raw_fd`](Self::as_raw_fd) to
+    /// become readable, then call [`check`](Self::check).
+    pub fn prepare(&self) -> bool {
+        #[cfg(feature = "alloc")]
+        if !self.stashed.borrow().is_empty() || !self.split.borrow().is_empty() {
+            return true;
+        }
+        self.pending.get().is_some() || !self.batch.borrow().is_empty()
+    }
+    // The interest `fd` is subscribed with, whatever its user data.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn subscribed_flags(&self, fd: usize) -> Option<EventFlags> {
+        self.subscriptions.borrow().get(&fd).map(|sub| sub.flags)
+    }
+    // The flags of the events for `fd` that were read from the kernel but not yet returned.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn held_flags(&self, fd: usize) -> EventFlags {
+        self.pending
+            .get()
+            .into_iter()
+            .chain(self.batch.borrow().as_slice().iter().copied())
+            .chain(self.split.borrow().iter().copied())
+            .chain(self.stashed.borrow().iter().copied())
+            .filter(|raw| raw.fd == fd)
+            .fold(EventFlags::empty(), |flags, raw| {
+                flags | EventFlags::from_bits_retain(raw.flags)
+            })
+    }
+    /// Second phase of a foreign main loop iteration: return whether events are ready to dispatch
+    ///
+    /// This never blocks. An event read here is kept until the next [`dispatch`](Self::dispatch)
+    /// or `next_event`.
+    pub fn check(&self) -> Result<bool> {
+        if !self.prepare() {
+            self.pending.set(self.try_next_raw_event()?);
+        }
+        Ok(self.prepare())
+    }
+    /// Block until events are available, and merge every ready event into `map`, by fd
+    ///
+    /// The flags of the events for each fd are combined with those already in `map`, which is not
+    /// cleared first. Returns the number of events merged, which is at least one. Events merged
+    /// before an error stay in `map`.
+    #[cfg(feature = "alloc")]
+    pub fn wait_grouped(&self, map: &mut BTreeMap<usize, EventFlags>) -> Result<usize> {
+        let mut count = 0;
+        let mut next = Some(self.next_raw_event()?);
+        while let Some(raw) = next {
+            *map.entry(raw.fd).or_default() |= EventFlags::from_bits_retain(raw.flags);
+            count += 1;
+            next = self.try_next_raw_event()?;
+        }
+        Ok(count)
+    }
+    /// Block until at least one event is ready, or until `timeout` has passed, without taking it
+    ///
+    /// Returns whether an event is ready, in which case the next `next_event`, `try_next_event`
+    /// or [`dispatch`](Self::dispatch) returns it without blocking. This is [`check`](Self::check)
+    /// with a timeout. The v1 ABI cannot wait with an empty buffer, since it returns zero events
+    /// both when the timeout expires and when events are pending, so an event may be read from
+    /// the kernel and kept by the queue.
+    pub fn wait_any(&self, timeout: impl Into<Timeout>) -> Result<bool> {
+        if !self.prepare() {
+            self.pending
+                .set(self.next_raw_event_timeout(Some(timeout.into()))?);
+        }
+        Ok(self.prepare())
+    }
+    /// Final phase of a foreign main loop iteration: pass every event that is ready to `f`
+    ///
+    /// This never blocks. Returns the number of events dispatched.
+    pub fn dispatch(&self, mut f: impl FnMut(Event<U>)) -> Result<usize> {
+        let mut count = 0;
+        while let Some(raw) = self.try_next_raw_event()? {
+            f(Self::event_from_raw(raw)?);
+            count += 1;
+        }
+        Ok(count)
+    }
     pub fn iter(&self) -> impl Iterator<Item = Result<Event<U>>> + '_ {
         core::iter::from_fn(|| Some(self.next_event()))
     }
+    /// Write a human-readable description of the queue and its subscriptions to `w`
+    ///
+    /// This does not allocate or make any syscalls, so it can be called from panic hooks. The
+    /// subscription table is only tracked when the `alloc` feature is enabled.
+    pub fn dump(&self, mut w: impl fmt::Write) -> fmt::Result {
+        writeln!(w, "event queue {:?}:", self.inner.inner)?;
+
+        #[cfg(feature = "alloc")]
+        match self.subscriptions.try_borrow() {
+            Ok(subscriptions) => {
+                writeln!(w, "  {} subscriptions", subscriptions.len())?;
+                for (fd, sub) in subscriptions.iter() {
+                    writeln!(
+                        w,
+                        "    fd {}: user_data={:#x} flags={:?}",
+                        fd, sub.user_data, sub.flags
+                    )?;
+                }
+            }
+            Err(_) => writeln!(w, "  subscriptions: <being modified>")?,
+        }
+        #[cfg(not(feature = "alloc"))]
+        writeln!(w, "  subscriptions: <untracked>")?;
+
+        if let Some(raw) = self.pending.get() {
+            writeln!(
+                w,
+                "  pending: fd {}: user_data={:#x} flags={:?}",
+                raw.fd,
+                raw.user_data,
+                EventFlags::from_bits_retain(raw.flags)
+            )?;
+        }
+        if let Ok(batch) = self.batch.try_borrow() {
+            for raw in batch.as_slice() {
+                writeln!(
+                    w,
+                    "  batched: fd {}: user_data={:#x} flags={:?}",
+                    raw.fd,
+                    raw.user_data,
+                    EventFlags::from_bits_retain(raw.flags)
+                )?;
+            }
+        }
+        #[cfg(feature = "alloc")]
+        if let Ok(stashed) = self.stashed.try_borrow() {
+            for raw in stashed.iter() {
+                writeln!(
+                    w,
+                    "  stashed: fd {}: user_data={:#x} flags={:?}",
+                    raw.fd,
+                    raw.user_data,
+                    EventFlags::from_bits_retain(raw.flags)
+                )?;
+            }
+        }
+        #[cfg(feature = "std")]
+        if let Some(Ok(history)) = self.history.as_ref().map(RefCell::try_borrow) {
+            history.dump(&mut w)?;
+        }
+
+        Ok(())
+    }
+}
+#[cfg(any(target_pointer_width = "64", feature = "alloc"))]
+impl EventQueue<usize> {
+    /// Subscribe to events produced by `fd`, identified by both a `token` and a `tag`
+    ///
+    /// On 64-bit targets both words are packed into the user data. Elsewhere, they are kept in a
+    /// side table keyed by `fd`, which requires the `alloc` feature. Events for such subscriptions
+    /// must be read with [`next_event2`](Self::next_event2).
+    ///
+    /// On 64-bit targets, a `token` of `u32::MAX` combined with one of the largest `tag` values
+    /// packs into [reserved](MAX_APP_VALUE) user data, and fails with `EINVAL`.
+    pub fn subscribe2(&self, fd: usize, token: u32, tag: u32, flags: EventFlags) -> Result<()> {
+        #[cfg(target_pointer_width = "64")]
+        {
+            self.subscribe(fd, ((token as usize) << 32) | tag as usize, flags)
+        }
+        #[cfg(not(target_pointer_width = "64"))]
+        {
+            self.subscribe(fd, fd, flags)?;
+            if !flags.is_empty() {
+                self.tags.borrow_mut().insert(fd, (token, tag));
+            }
+            Ok(())
+        }
+    }
+    /// Block until the next event for a [`subscribe2`](Self::subscribe2) subscription
+    pub fn next_event2(&self) -> Result<Event2> {
+        let raw = self.next_raw_event()?;
+
+        #[cfg(target_pointer_width = "64")]
+        let (token, tag) = ((raw.user_data >> 32) as u32, raw.user_data as u32);
+        #[cfg(not(target_pointer_width = "64"))]
+        let (token, tag) = self.tags.borrow().get(&raw.fd).copied().unwrap_or_default();
+
+        Ok(Event2 {
+            token,
+            tag,
+            flags: EventFlags::from_bits_retain(raw.flags),
+            fd: raw.fd,
+        })
+    }
+}
+/// A set of subscriptions on an [`EventQueue`] that are paused and resumed together
+///
+/// Pausing only stops the kernel from reporting the members' events: the queue keeps their
+/// subscriptions, interests and nonblocking mode, and still reports them through
+/// [`subscription`](EventQueue::subscription). Dropping the group leaves its subscriptions in
+/// place, resuming them if it is paused.
+#[cfg(feature = "alloc")]
+pub struct Group<'a, U: UserData> {
+    queue: &'a EventQueue<U>,
+    members: RefCell<BTreeSet<usize>>,
+    paused: Cell<bool>,
+}
+
+#[cfg(feature = "alloc")]
+impl<U: UserData> Group<'_, U> {
+    /// Subscribe to events produced by `fd`, as a member of this group
+    ///
+    /// If the group is paused, events are only reported once it is resumed.
+    pub fn subscribe(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        self.queue.subscribe(fd, data, flags)?;
+        if self.paused.get() {
+            if let Err(err) = self.queue.retarget(fd, EventFlags::empty()) {
+                let _ = self.queue.unsubscribe(fd);
+                return Err(err);
+            }
+        }
+        self.members.borrow_mut().insert(fd);
+        Ok(())
+    }
+    /// Unsubscribe from events produced by `fd`, and remove it from the group
+    pub fn unsubscribe(&self, fd: usize) -> Result<()> {
+        if self.members.borrow_mut().remove(&fd) {
+            self.queue.unsubscribe(fd)?;
+        }
+        Ok(())
+    }
+    /// Stop reporting the events of every member, until [`resume`](Self::resume) is called
+    ///
+    /// If pausing a member fails, those already paused are resumed, and the group stays
+    /// resumed.
+    pub fn pause(&self) -> Result<()> {
+        if self.paused.get() {
+            return Ok(());
+        }
+        let members = self.members.borrow();
+        for (i, &fd) in members.iter().enumerate() {
+            if let Err(err) = self.queue.retarget(fd, EventFlags::empty()) {
+                for &fd in members.iter().take(i) {
+                    let _ = self.queue.restore(fd);
+                }
+                return Err(err);
+            }
+        }
+        self.paused.set(true);
+        Ok(())
+    }
+    /// Report the events of every member again after [`pause`](Self::pause)
+    ///
+    /// If resuming a member fails, those already resumed are paused again, and the group stays
+    /// paused.
+    pub fn resume(&self) -> Result<()> {
+        if !self.paused.get() {
+            return Ok(());
+        }
+        let members = self.members.borrow();
+        for (i, &fd) in members.iter().enumerate() {
+            if let Err(err) = self.queue.restore(fd) {
+                for &fd in members.iter().take(i) {
+                    let _ = self.queue.retarget(fd, EventFlags::empty());
+                }
+                return Err(err);
+            }
+        }
+        self.paused.set(false);
+        Ok(())
+    }
+    /// Unsubscribe and remove every member
+    pub fn clear(&self) -> Result<()> {
+        let members = mem::take(&mut *self.members.borrow_mut());
+        for &fd in &members {
+            self.queue.unsubscribe(fd)?;
+        }
+        Ok(())
+    }
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused.get()
+    }
+    /// The number of members
+    pub fn len(&self) -> usize {
+        self.members.borrow().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.members.borrow().is_empty()
+    }
+}
+#[cfg(feature = "alloc")]
+impl<U: UserData> Drop for Group<'_, U> {
+    fn drop(&mut self) {
+        if self.paused.get() {
+            for &fd in self.members.get_mut().iter() {
+                let _ = self.queue.restore(fd);
+            }
+        }
+    }
+}
+/// Subscription changes to an [`EventQueue`] that are either all applied, or not at all
+#[cfg(feature = "alloc")]
+#[must_use = "changes are only applied by commit"]
+pub struct Transaction<'a, U: UserData> {
+    queue: &'a EventQueue<U>,
+    // Unsubscribing is subscribing with empty flags.
+    ops: Vec<(usize, DetachedSubscription)>,
+}
+
+#[cfg(feature = "alloc")]
+impl<U: UserData> Transaction<'_, U> {
+    /// Subscribe to, or change the subscription of, `fd`
+    pub fn subscribe(&mut self, fd: usize, data: U, flags: EventFlags) -> &mut Self {
+        let sub = DetachedSubscription {
+            user_data: data.into_user_data(),
+            flags,
+        };
+        self.ops.push((fd, sub));
+        self
+    }
+    /// Unsubscribe from `fd`
+    pub fn unsubscribe(&mut self, fd: usize) -> &mut Self {
+        let sub = DetachedSubscription {
+            user_data: 0,
+            flags: EventFlags::empty(),
+        };
+        self.ops.push((fd, sub));
+        self
+    }
+    /// Apply the changes in order
+    ///
+    /// If one fails, the changes applied before it are reverted as far as possible, and its error
+    /// is returned.
+    pub fn commit(self) -> Result<()> {
+        let mut applied = Vec::with_capacity(self.ops.len());
+        for &(fd, sub) in &self.ops {
+            let saved = self.save(fd);
+            if let Err(err) = self.queue.attach(fd, sub) {
+                for (fd, saved) in applied.into_iter().rev() {
+                    self.rollback(fd, saved);
+                }
+                return Err(err);
+            }
+            applied.push((fd, saved));
+        }
+        Ok(())
+    }
+    // Replacing a subscription drops its interests, and removing it also its fire count and
+    // nonblocking mode, all of which must come back with it.
+    fn save(&self, fd: usize) -> Saved {
+        let queue = self.queue;
+        Saved {
+            previous: queue.subscriptions.borrow().get(&fd).copied(),
+            interests: queue.interests.borrow().get(&fd).cloned(),
+            nonblocking: queue.nonblocking.borrow().contains(&fd),
+            fire_count: queue
+                .fire_counts
+                .as_ref()
+                .and_then(|counts| counts.borrow().get(&fd).copied()),
+        }
+    }
+    fn rollback(&self, fd: usize, saved: Saved) {
+        let queue = self.queue;
+        let res = match saved.previous {
+            Some(Subscription { user_data, flags }) => queue.subscribe_raw(fd, user_data, flags),
+            None => queue.unsubscribe(fd),
+        };
+        if res.is_err() {
+            return;
+        }
+        if let Some(interests) = saved.interests {
+            queue.interests.borrow_mut().insert(fd, interests);
+        }
+        if saved.nonblocking
+            && !queue.nonblocking.borrow().contains(&fd)
+            && set_nonblocking(fd, true).is_ok()
+        {
+            queue.nonblocking.borrow_mut().insert(fd);
+        }
+        if let (Some(counts), Some(count)) = (&queue.fire_counts, saved.fire_count) {
+            counts.borrow_mut().insert(fd, count);
+        }
+    }
+}
+// The state of an fd changed by a transaction, as it was before.
+#[cfg(feature = "alloc")]
+struct Saved {
+    previous: Option<Subscription>,
+    interests: Option<Vec<(usize, EventFlags)>>,
+    nonblocking: bool,
+    fire_count: Option<u64>,
+}
+/// A subscription whose user data and interest are chosen before its fd exists
+///
+/// Created by [`EventQueue::reserve`], and made with [`bind`](Self::bind) once the fd is open.
+#[must_use = "nothing is subscribed until bind is called"]
+pub struct PendingRegistration<'a, U: UserData> {
+    queue: &'a EventQueue<U>,
+    data: U,
+    flags: EventFlags,
+}
+
+impl<U: UserData> PendingRegistration<'_, U> {
+    #[inline]
+    pub fn data(&self) -> U {
+        self.data
+    }
+    #[inline]
+    pub fn flags(&self) -> EventFlags {
+        self.flags
+    }
+    /// Subscribe `fd` with the reserved user data and interest
+    pub fn bind(self, fd: usize) -> Result<()> {
+        self.queue.subscribe(fd, self.data, self.flags)
+    }
 }
 impl<U: UserData> Iterator for EventQueue<U> {
     type Item = Result<Event<U>>;
@@ -171,3 +1998,47 @@ impl<U: UserData> Iterator for EventQueue<U> {
         Some(self.next_event())
     }
 }
+
+/// Common interface of event queue implementations, allowing code to be generic over the backend
+///
+/// Besides the kernel-backed queues, it is implemented by [`MockQueue`](crate::MockQueue) with the
+/// `alloc` feature, so such code can be tested without any fds.
+pub trait EventQueueApi<U: UserData> {
+    /// Subscribe to events produced by `fd`
+    fn subscribe(&self, fd: usize, data: U, flags: EventFlags) -> Result<()>;
+    /// Unsubscribe from events produced by `fd`
+    fn unsubscribe(&self, fd: usize) -> Result<()>;
+    /// Block until the next event is available
+    fn wait(&self) -> Result<Event<U>>;
+}
+impl EventQueueApi<usize> for RawEventQueue {
+    #[inline]
+    fn subscribe(&self, fd: usize, data: usize, flags: EventFlags) -> Result<()> {
+        RawEventQueue::subscribe(self, fd, data, flags)
+    }
+    #[inline]
+    fn unsubscribe(&self, fd: usize) -> Result<()> {
+        RawEventQueue::unsubscribe(self, fd)
+    }
+    fn wait(&self) -> Result<Event<usize>> {
+        self.next_event().map(|raw| Event {
+            user_data: raw.user_data,
+            fd: raw.fd,
+            flags: EventFlags::from_bits_retain(raw.flags),
+        })
+    }
+}
+impl<U: UserData> EventQueueApi<U> for EventQueue<U> {
+    #[inline]
+    fn subscribe(&self, fd: usize, data: U, flags: EventFlags) -> Result<()> {
+        EventQueue::subscribe(self, fd, data, flags)
+    }
+    #[inline]
+    fn unsubscribe(&self, fd: usize) -> Result<()> {
+        EventQueue::unsubscribe(self, fd)
+    }
+    #[inline]
+    fn wait(&self) -> Result<Event<U>> {
+        self.next_event()
+    }
+}
@@ -0,0 +1,105 @@
+//! Buffered writing to an fd, driven by writable events.
+use std::io::{self, ErrorKind, Write};
+use std::os::fd::AsRawFd;
+use std::vec::Vec;
+
+use crate::wrappers::io_error;
+use crate::{EventFlags, EventQueue, UserData};
+
+/// A writer that buffers output and writes it out as its fd becomes writable
+///
+/// Interest in write events is only registered on the queue while data is buffered, so an idle
+/// writer never wakes the loop up. The writer should be nonblocking, and events for `data` must
+/// be passed to [`on_writable`](Self::on_writable).
+///
+/// The interest is added with [`EventQueue::subscribe_interest`], so the fd can also be read from
+/// through another interest with different user data. Subscribing the fd with
+/// [`EventQueue::subscribe`] instead would replace the writer's interest.
+pub struct EventBufWriter<'q, U: UserData, W: Write + AsRawFd> {
+    queue: &'q EventQueue<U>,
+    data: U,
+    writer: W,
+    buf: Vec<u8>,
+    subscribed: bool,
+}
+
+impl<'q, U: UserData, W: Write + AsRawFd> EventBufWriter<'q, U, W> {
+    pub fn new(queue: &'q EventQueue<U>, writer: W, data: U) -> Self {
+        Self {
+            queue,
+            data,
+            writer,
+            buf: Vec::new(),
+            subscribed: false,
+        }
+    }
+    /// Buffer `bytes`, to be written once the fd is writable
+    pub fn queue_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        self.buf.extend_from_slice(bytes);
+        if !self.subscribed {
+            self.queue
+                .subscribe_interest(self.fd(), self.data, EventFlags::WRITE)
+                .map_err(io_error)?;
+            self.subscribed = true;
+        }
+        Ok(())
+    }
+    /// Write as much buffered data as possible, after a writable event
+    ///
+    /// Interest in write events is dropped once the buffer is empty.
+    pub fn on_writable(&mut self) -> io::Result<()> {
+        while !self.buf.is_empty() {
+            match self.writer.write(&self.buf) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(count) => {
+                    self.buf.drain(..count);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        if self.subscribed {
+            self.queue
+                .unsubscribe_interest(self.fd(), self.data)
+                .map_err(io_error)?;
+            self.subscribed = false;
+        }
+        Ok(())
+    }
+    /// The number of bytes waiting to be written
+    #[inline]
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+    fn fd(&self) -> usize {
+        self.writer.as_raw_fd() as usize
+    }
+}
+impl<U: UserData, W: Write + AsRawFd> Write for EventBufWriter<'_, U, W> {
+    /// Buffer all of `buf`, never blocking
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.queue_bytes(buf)?;
+        Ok(buf.len())
+    }
+    /// Write as much buffered data as the fd accepts without blocking
+    ///
+    /// This does not wait for the buffer to be emptied.
+    fn flush(&mut self) -> io::Result<()> {
+        self.on_writable()
+    }
+}
+impl<U: UserData, W: Write + AsRawFd> Drop for EventBufWriter<'_, U, W> {
+    fn drop(&mut self) {
+        if self.subscribed {
+            let _ = self.queue.unsubscribe_interest(self.fd(), self.data);
+        }
+    }
+}
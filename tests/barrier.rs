@@ -0,0 +1,65 @@
+//! Visibility of events generated before `EventQueue::barrier`.
+#![cfg(all(target_os = "redox", feature = "wrappers", feature = "alloc"))]
+
+use std::io::{pipe, Write};
+use std::os::fd::AsRawFd;
+
+use event::{EventFlags, EventQueue};
+
+#[test]
+fn pipe_write_is_visible_after_barrier() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    queue.subscribe(fd, 1, EventFlags::READ).unwrap();
+
+    assert_eq!(queue.barrier().unwrap(), 0);
+    assert!(!queue.prepare());
+
+    writer.write_all(&[0]).unwrap();
+    assert_eq!(queue.barrier().unwrap(), 1);
+    assert!(queue.prepare());
+
+    let event = queue.try_next_event().unwrap().unwrap();
+    assert_eq!((event.fd, event.user_data), (fd, 1));
+    assert!(!queue.prepare());
+}
+
+#[test]
+fn barrier_keeps_order() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (first, mut first_writer) = pipe().unwrap();
+    let (second, mut second_writer) = pipe().unwrap();
+    queue
+        .subscribe(first.as_raw_fd() as usize, 1, EventFlags::READ)
+        .unwrap();
+    queue
+        .subscribe(second.as_raw_fd() as usize, 2, EventFlags::READ)
+        .unwrap();
+
+    first_writer.write_all(&[0]).unwrap();
+    assert!(queue.check().unwrap());
+    second_writer.write_all(&[0]).unwrap();
+    queue.barrier().unwrap();
+
+    let order: Vec<usize> = (0..2)
+        .map(|_| queue.try_next_event().unwrap().unwrap().user_data)
+        .collect();
+    assert_eq!(order, [1, 2]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn barrier_keeps_interrupts_from_the_filter() {
+    let mut queue = EventQueue::<usize>::new().unwrap();
+    queue.set_filter(|_| false);
+    let handle = queue.interrupt_handle().unwrap();
+
+    handle.interrupt();
+    queue.barrier().unwrap();
+    match queue.next_event() {
+        Ok(_) => panic!("the interrupt was lost"),
+        Err(err) => assert_eq!(err.errno(), event::libredox::errno::EINTR),
+    }
+    assert_eq!(queue.filtered_count(), 0);
+}
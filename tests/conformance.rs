@@ -0,0 +1,150 @@
+//! Conformance of the kernel to the raw event queue ABI.
+//!
+//! These exercise the raw calls directly, so they only run on Redox.
+#![cfg(all(target_os = "redox", feature = "wrappers"))]
+
+use std::io::{pipe, PipeReader, PipeWriter, Write};
+use std::mem::{self, MaybeUninit};
+use std::os::fd::AsRawFd;
+use std::ptr;
+
+use event::libredox::data::{SigSet, TimeSpec};
+use event::libredox::errno::EBADF;
+use event::libredox::error::{Error, Result};
+use event::raw::{self, EventFlags, RawEventV1};
+
+const ZERO: TimeSpec = TimeSpec {
+    tv_sec: 0,
+    tv_nsec: 0,
+};
+// An fd that is never open.
+const BAD_FD: usize = usize::MAX - 1;
+
+struct Queue(usize);
+
+impl Queue {
+    fn new() -> Self {
+        Self(Error::demux(unsafe { raw::redox_event_queue_create_v1(0) }).unwrap())
+    }
+    fn ctl(&self, fd: usize, flags: EventFlags, user_data: usize) -> Result<usize> {
+        Error::demux(unsafe { raw::redox_event_queue_ctl_v1(self.0, fd, flags.bits(), user_data) })
+    }
+    fn get(
+        &self,
+        buf: &mut [MaybeUninit<RawEventV1>],
+        timeout: Option<&TimeSpec>,
+        sigset: Option<&SigSet>,
+    ) -> Result<usize> {
+        Error::demux(unsafe {
+            raw::redox_event_queue_get_events_v1(
+                self.0,
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                0,
+                timeout.map_or(ptr::null(), |timeout| timeout),
+                sigset.map_or(ptr::null(), |sigset| sigset),
+            )
+        })
+    }
+}
+impl Drop for Queue {
+    fn drop(&mut self) {
+        Error::demux(unsafe { raw::redox_event_queue_destroy_v1(self.0) }).unwrap();
+    }
+}
+
+// A queue with a readable pipe subscribed with user data 42.
+fn readable() -> (Queue, PipeReader, PipeWriter) {
+    let queue = Queue::new();
+    let (reader, mut writer) = pipe().unwrap();
+    queue
+        .ctl(reader.as_raw_fd() as usize, EventFlags::READ, 42)
+        .unwrap();
+    writer.write_all(&[0]).unwrap();
+    (queue, reader, writer)
+}
+
+fn errno<T>(res: Result<T>) -> Option<i32> {
+    res.err().map(Error::errno)
+}
+
+#[test]
+fn bad_queue_handle() {
+    let mut buf = [MaybeUninit::<RawEventV1>::uninit()];
+    unsafe {
+        assert_eq!(
+            errno(Error::demux(raw::redox_event_queue_ctl_v1(
+                BAD_FD,
+                0,
+                EventFlags::READ.bits(),
+                0
+            ))),
+            Some(EBADF)
+        );
+        assert_eq!(
+            errno(Error::demux(raw::redox_event_queue_get_events_v1(
+                BAD_FD,
+                buf.as_mut_ptr().cast(),
+                1,
+                0,
+                &ZERO,
+                ptr::null(),
+            ))),
+            Some(EBADF)
+        );
+        assert_eq!(
+            errno(Error::demux(raw::redox_event_queue_destroy_v1(BAD_FD))),
+            Some(EBADF)
+        );
+    }
+}
+
+#[test]
+fn subscribe_bad_fd() {
+    let queue = Queue::new();
+    assert_eq!(errno(queue.ctl(BAD_FD, EventFlags::READ, 0)), Some(EBADF));
+}
+
+#[test]
+fn zero_timeout_without_events() {
+    let queue = Queue::new();
+    let mut buf = [MaybeUninit::uninit(); 4];
+    assert_eq!(queue.get(&mut buf, Some(&ZERO), None), Ok(0));
+}
+
+#[test]
+fn zero_length_buffer() {
+    let (queue, _reader, _writer) = readable();
+    assert_eq!(queue.get(&mut [], Some(&ZERO), None), Ok(0));
+
+    // The event must not have been consumed.
+    let mut buf = [MaybeUninit::uninit()];
+    assert_eq!(queue.get(&mut buf, Some(&ZERO), None), Ok(1));
+}
+
+#[test]
+fn null_timeout_returns_ready_event() {
+    let (queue, reader, _writer) = readable();
+    let mut buf = [MaybeUninit::uninit()];
+    assert_eq!(queue.get(&mut buf, None, None), Ok(1));
+
+    let event = unsafe { buf[0].assume_init() };
+    assert_eq!(event.fd, reader.as_raw_fd() as usize);
+    assert_eq!(event.user_data, 42);
+    assert!(EventFlags::from_bits_retain(event.flags).contains(EventFlags::READ));
+}
+
+#[test]
+fn count_larger_than_pending() {
+    let (queue, _reader, _writer) = readable();
+    let mut buf = [MaybeUninit::uninit(); 64];
+    assert_eq!(queue.get(&mut buf, Some(&ZERO), None), Ok(1));
+}
+
+#[test]
+fn empty_sigset() {
+    let (queue, _reader, _writer) = readable();
+    let sigset: SigSet = unsafe { mem::zeroed() };
+    let mut buf = [MaybeUninit::uninit()];
+    assert_eq!(queue.get(&mut buf, Some(&ZERO), Some(&sigset)), Ok(1));
+}
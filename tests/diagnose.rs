@@ -0,0 +1,29 @@
+//! Diagnosing the readiness of subscribed fds.
+#![cfg(all(target_os = "redox", feature = "wrappers", feature = "alloc"))]
+
+use std::io::pipe;
+use std::os::fd::AsRawFd;
+
+use event::{DetachedSubscription, EventFlags, EventQueue};
+
+event::user_data! {
+    enum Token {
+        Only,
+    }
+}
+
+#[test]
+fn diagnose_ignores_undecodable_user_data() {
+    let queue = EventQueue::<Token>::new().unwrap();
+    let (reader, _writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    let sub = DetachedSubscription {
+        user_data: 5,
+        flags: EventFlags::READ,
+    };
+    queue.attach(fd, sub).unwrap();
+
+    let diagnosis = queue.diagnose(fd).unwrap();
+    assert_eq!(diagnosis.subscribed, Some(EventFlags::READ));
+    assert!(diagnosis.is_consistent());
+}
@@ -0,0 +1,75 @@
+//! Bounding the work done by a single iteration of an event loop.
+#![cfg(all(target_os = "redox", feature = "wrappers", feature = "alloc"))]
+
+use std::cell::Cell;
+use std::io::{pipe, Write};
+use std::os::fd::AsRawFd;
+use std::rc::Rc;
+
+use event::{EventFlags, EventLoop};
+
+// An fd that is never open.
+const BAD_FD: usize = usize::MAX - 1;
+
+#[test]
+fn dispatch_is_bounded_by_the_batch() {
+    let mut event_loop = EventLoop::<usize>::new().unwrap();
+    event_loop.set_max_dispatch_batch(1);
+    let (first, mut first_writer) = pipe().unwrap();
+    let (second, mut second_writer) = pipe().unwrap();
+    let queue = event_loop.queue();
+    queue
+        .subscribe(first.as_raw_fd() as usize, 1, EventFlags::READ)
+        .unwrap();
+    queue
+        .subscribe(second.as_raw_fd() as usize, 2, EventFlags::READ)
+        .unwrap();
+    first_writer.write_all(&[0]).unwrap();
+    second_writer.write_all(&[0]).unwrap();
+    queue.barrier().unwrap();
+
+    let deferred = Rc::new(Cell::new(0));
+    let mut seen = Vec::new();
+    for _ in 0..2 {
+        let count = event_loop
+            .run_once(|handle, event| {
+                seen.push(event.user_data);
+                let deferred = Rc::clone(&deferred);
+                handle.defer(move |_| deferred.set(deferred.get() + 1));
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(deferred.get(), seen.len());
+    }
+    seen.sort();
+    assert_eq!(seen, [1, 2]);
+}
+
+#[test]
+fn failed_await_keeps_other_work() {
+    let mut event_loop = EventLoop::<usize>::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    let (other, mut other_writer) = pipe().unwrap();
+    let other_fd = other.as_raw_fd() as usize;
+    event_loop
+        .queue()
+        .subscribe(reader.as_raw_fd() as usize, 1, EventFlags::READ)
+        .unwrap();
+
+    let woken = Rc::new(Cell::new(false));
+    let deferred = Rc::new(Cell::new(false));
+    writer.write_all(&[0]).unwrap();
+    let res = event_loop.run_once(|handle, _| {
+        handle.await_readable(BAD_FD, |_, _| panic!("woken for a closed fd"));
+        let woken = Rc::clone(&woken);
+        handle.await_readable(other_fd, move |_, _| woken.set(true));
+        let deferred = Rc::clone(&deferred);
+        handle.defer(move |_| deferred.set(true));
+    });
+    assert!(res.is_err());
+    assert!(deferred.get());
+
+    other_writer.write_all(&[0]).unwrap();
+    event_loop.run_once(|_, _| {}).unwrap();
+    assert!(woken.get());
+}
@@ -0,0 +1,46 @@
+//! Conversions between `EventFlags` and `syscall::EventFlags`, over every bit.
+#![cfg(feature = "redox_syscall")]
+
+use event::raw::EventFlags;
+use syscall::flag::EventFlags as SyscallFlags;
+
+const KNOWN: u32 = EventFlags::all().bits();
+
+#[test]
+fn known_flags_round_trip() {
+    for bits in 0..=KNOWN {
+        let Some(flags) = EventFlags::from_bits(bits) else {
+            continue;
+        };
+        let converted = SyscallFlags::from(flags);
+        assert_eq!(converted.bits(), bits as usize);
+        assert_eq!(EventFlags::from(converted), flags);
+    }
+}
+
+#[test]
+fn unknown_bits_are_dropped() {
+    for bit in 0..u32::BITS {
+        let bits = 1 << bit;
+        let flags = EventFlags::from_bits_retain(bits);
+        let expected = EventFlags::from_bits_truncate(bits);
+        assert_eq!(EventFlags::from(SyscallFlags::from(flags)), expected);
+    }
+    for bit in 0..usize::BITS {
+        let bits = 1 << bit;
+        let flags = SyscallFlags::from_bits_retain(bits);
+        assert_eq!(
+            SyscallFlags::from(EventFlags::from(flags)).bits(),
+            bits & KNOWN as usize
+        );
+    }
+}
+
+#[test]
+fn all_bits_set() {
+    let flags = EventFlags::from_bits_retain(u32::MAX);
+    assert_eq!(SyscallFlags::from(flags).bits(), KNOWN as usize);
+
+    let flags = SyscallFlags::from_bits_retain(usize::MAX);
+    assert_eq!(EventFlags::from(flags), EventFlags::all());
+}
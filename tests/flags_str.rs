@@ -0,0 +1,37 @@
+//! Formatting and parsing of `EventFlags`.
+use event::raw::EventFlags;
+
+#[test]
+fn display() {
+    assert_eq!(EventFlags::empty().to_string(), "none");
+    assert_eq!(EventFlags::READ.to_string(), "read");
+    assert_eq!(
+        (EventFlags::READ | EventFlags::WRITE).to_string(),
+        "read|write"
+    );
+    assert_eq!(EventFlags::from_bits_retain(0x11).to_string(), "read|0x10");
+}
+
+#[test]
+fn parse() {
+    assert_eq!("none".parse(), Ok(EventFlags::empty()));
+    assert_eq!(
+        " Read | WRITE ".parse(),
+        Ok(EventFlags::READ | EventFlags::WRITE)
+    );
+    assert_eq!(
+        "write,read".parse(),
+        Ok(EventFlags::READ | EventFlags::WRITE)
+    );
+    assert_eq!("0x10".parse(), Ok(EventFlags::from_bits_retain(0x10)));
+    assert!("read|edge".parse::<EventFlags>().is_err());
+    assert!("".parse::<EventFlags>().is_err());
+}
+
+#[test]
+fn round_trip() {
+    for bits in [0, 1, 2, 3, 0x80, 0xffff_ffff] {
+        let flags = EventFlags::from_bits_retain(bits);
+        assert_eq!(flags.to_string().parse(), Ok(flags));
+    }
+}
@@ -0,0 +1,73 @@
+//! Pausing and resuming subscription groups.
+#![cfg(all(target_os = "redox", feature = "wrappers", feature = "alloc"))]
+
+use std::io::{pipe, Write};
+use std::os::fd::AsRawFd;
+
+use event::libredox::errno::EINVAL;
+use event::{EventFlags, EventQueue};
+
+#[test]
+fn paused_subscribe_checks_user_data() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (reader, _writer) = pipe().unwrap();
+    let group = queue.group();
+    group.pause().unwrap();
+
+    let fd = reader.as_raw_fd() as usize;
+    match group.subscribe(fd, usize::MAX, EventFlags::READ) {
+        Ok(()) => panic!("reserved user data was accepted"),
+        Err(err) => assert_eq!(err.errno(), EINVAL),
+    }
+    assert!(group.is_empty());
+}
+
+#[test]
+fn failed_pause_keeps_members_subscribed() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (first, _first_writer) = pipe().unwrap();
+    let (second, _second_writer) = pipe().unwrap();
+    let (first_fd, second_fd) = (first.as_raw_fd() as usize, second.as_raw_fd() as usize);
+    let group = queue.group();
+    group.subscribe(first_fd, 1, EventFlags::READ).unwrap();
+    group.subscribe(second_fd, 2, EventFlags::READ).unwrap();
+
+    // Unsubscribing the closed fd fails, after the other one was unsubscribed.
+    drop(if first_fd < second_fd { second } else { first });
+    assert!(group.pause().is_err());
+    assert!(!group.is_paused());
+    let open_fd = first_fd.min(second_fd);
+    assert!(queue.subscription(open_fd).unwrap().is_some());
+}
+
+#[test]
+fn paused_members_keep_their_subscription() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    let group = queue.group();
+    group.subscribe(fd, 1, EventFlags::READ).unwrap();
+    group.pause().unwrap();
+
+    writer.write_all(&[0]).unwrap();
+    assert_eq!(queue.barrier().unwrap(), 0);
+    assert_eq!(queue.subscription(fd).unwrap(), Some((1, EventFlags::READ)));
+
+    group.resume().unwrap();
+    writer.write_all(&[0]).unwrap();
+    assert_eq!(queue.next_event().unwrap().user_data, 1);
+}
+
+#[test]
+fn dropping_a_paused_group_resumes_it() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    let group = queue.group();
+    group.subscribe(fd, 1, EventFlags::READ).unwrap();
+    group.pause().unwrap();
+    drop(group);
+
+    writer.write_all(&[0]).unwrap();
+    assert_eq!(queue.next_event().unwrap().user_data, 1);
+}
@@ -0,0 +1,140 @@
+//! Splitting of events for fds with several interests.
+#![cfg(all(target_os = "redox", feature = "wrappers", feature = "alloc"))]
+
+use std::cell::Cell;
+use std::io::{pipe, Read, Write};
+use std::os::fd::AsRawFd;
+#[cfg(feature = "std")]
+use std::os::unix::net::UnixStream;
+use std::rc::Rc;
+
+use event::libredox::errno::EBUSY;
+use event::{DetachedSubscription, EventFlags, EventLoop, EventQueue};
+
+#[test]
+fn attach_replaces_interests() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    queue.subscribe_interest(fd, 1, EventFlags::READ).unwrap();
+    queue.subscribe_interest(fd, 2, EventFlags::READ).unwrap();
+
+    queue
+        .attach(
+            fd,
+            DetachedSubscription {
+                user_data: 3,
+                flags: EventFlags::READ,
+            },
+        )
+        .unwrap();
+    assert_eq!(queue.subscription(fd).unwrap(), Some((3, EventFlags::READ)));
+
+    writer.write_all(&[0]).unwrap();
+    let event = queue.next_event().unwrap();
+    assert_eq!((event.fd, event.user_data), (fd, 3));
+    assert!(!queue.prepare());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn buf_writer_keeps_read_interest() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (local, mut remote) = UnixStream::pair().unwrap();
+    local.set_nonblocking(true).unwrap();
+    let fd = local.as_raw_fd() as usize;
+    queue.subscribe_interest(fd, 1, EventFlags::READ).unwrap();
+
+    let mut writer = event::EventBufWriter::new(&queue, local.try_clone().unwrap(), 2);
+    writer.queue_bytes(b"ping").unwrap();
+    let event = queue.next_event().unwrap();
+    assert_eq!((event.user_data, event.flags), (2, EventFlags::WRITE));
+    writer.on_writable().unwrap();
+    assert_eq!(writer.buffered(), 0);
+    assert_eq!(queue.subscription(fd).unwrap(), Some((1, EventFlags::READ)));
+
+    remote.write_all(b"pong").unwrap();
+    let event = queue.next_event().unwrap();
+    assert_eq!((event.user_data, event.flags), (1, EventFlags::READ));
+}
+
+#[test]
+fn failed_transaction_restores_interests() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    queue.subscribe_interest(fd, 1, EventFlags::READ).unwrap();
+    queue.subscribe_interest(fd, 2, EventFlags::READ).unwrap();
+
+    let mut transaction = queue.transaction();
+    // The second change fails, as the fd is never open.
+    transaction
+        .subscribe(fd, 3, EventFlags::READ)
+        .subscribe(usize::MAX - 1, 4, EventFlags::READ);
+    assert!(transaction.commit().is_err());
+
+    writer.write_all(&[0]).unwrap();
+    let mut data = [
+        queue.next_event().unwrap().user_data,
+        queue.next_event().unwrap().user_data,
+    ];
+    data.sort();
+    assert_eq!(data, [1, 2]);
+}
+
+#[test]
+fn await_restores_interests() {
+    let mut event_loop = EventLoop::<usize>::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    let (trigger, mut trigger_writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    let queue = event_loop.queue();
+    queue.subscribe_interest(fd, 1, EventFlags::READ).unwrap();
+    queue.subscribe_interest(fd, 2, EventFlags::READ).unwrap();
+    queue
+        .subscribe(trigger.as_raw_fd() as usize, 3, EventFlags::READ)
+        .unwrap();
+
+    let woken = Rc::new(Cell::new(false));
+    trigger_writer.write_all(&[0]).unwrap();
+    event_loop
+        .run_once(|handle, event| {
+            assert_eq!(event.user_data, 3);
+            let woken = Rc::clone(&woken);
+            handle.await_readable(fd, move |_, _| woken.set(true));
+        })
+        .unwrap();
+
+    writer.write_all(&[0]).unwrap();
+    event_loop
+        .run_once(|_, event| panic!("unexpected event for {}", event.fd))
+        .unwrap();
+    assert!(woken.get());
+    (&reader).read_exact(&mut [0]).unwrap();
+
+    writer.write_all(&[0]).unwrap();
+    let mut data = Vec::new();
+    while data.len() < 2 {
+        event_loop
+            .run_once(|_, event| data.push(event.user_data))
+            .unwrap();
+    }
+    data.sort();
+    assert_eq!(data, [1, 2]);
+}
+
+#[test]
+fn detach_refuses_interests() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    queue.subscribe_interest(fd, 1, EventFlags::READ).unwrap();
+
+    let Err(err) = queue.detach(fd) else {
+        panic!("detached an fd with interests");
+    };
+    assert_eq!(err.errno(), EBUSY);
+
+    writer.write_all(&[0]).unwrap();
+    assert_eq!(queue.next_event().unwrap().user_data, 1);
+}
@@ -0,0 +1,26 @@
+//! Formatting into `EventLine` without allocating.
+#![cfg(feature = "wrappers")]
+
+use std::fmt::Write;
+
+use event::raw::EventFlags;
+use event::EventLine;
+
+#[test]
+fn formats_flags() {
+    let mut line = EventLine::<32>::new();
+    write!(line, "fd {} {}", 3, EventFlags::READ | EventFlags::WRITE).unwrap();
+    assert_eq!(line.as_str(), "fd 3 read|write");
+    assert!(!line.is_truncated());
+}
+
+#[test]
+fn truncates_at_char_boundary() {
+    let mut line = EventLine::<5>::new();
+    line.write_str("abcdé").unwrap();
+    assert_eq!(line.as_str(), "abcd");
+    assert!(line.is_truncated());
+
+    line.write_str("x").unwrap();
+    assert_eq!(line.as_str(), "abcd");
+}
@@ -0,0 +1,122 @@
+//! Code generic over `EventQueueApi`, driven by a `MockQueue`.
+#![cfg(all(feature = "wrappers", feature = "alloc"))]
+
+use event::libredox::errno::{EAGAIN, EINVAL};
+use event::libredox::error::Result;
+use event::{Backend, DynQueue, EventFlags, EventQueueApi, EventQueueExt, MockQueue};
+
+event::user_data! {
+    enum Token {
+        Listener,
+        Client,
+    }
+}
+
+// A stand-in for application code written against the trait.
+struct Server {
+    accepted: usize,
+    read: usize,
+}
+
+impl Server {
+    fn register(&self, queue: &impl EventQueueApi<Token>) -> Result<()> {
+        queue.subscribe(3, Token::Listener, EventFlags::READ)?;
+        queue.subscribe(4, Token::Client, EventFlags::READ | EventFlags::WRITE)
+    }
+    fn poll(&mut self, queue: &impl EventQueueApi<Token>) -> Result<()> {
+        let event = queue.wait()?;
+        match event.user_data {
+            Token::Listener => self.accepted += 1,
+            Token::Client => self.read += 1,
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn generic_code_runs_on_mock() {
+    let queue = MockQueue::new();
+    let mut server = Server {
+        accepted: 0,
+        read: 0,
+    };
+    // Errors are not unwrapped, as their Debug calls into the system to describe them.
+    let Ok(()) = server.register(&queue) else {
+        panic!("register failed");
+    };
+    assert!(queue.post(3, EventFlags::READ));
+    assert!(queue.post(4, EventFlags::READ));
+    assert!(!queue.post(5, EventFlags::READ));
+    for _ in 0..2 {
+        let Ok(()) = server.poll(&queue) else {
+            panic!("poll failed");
+        };
+    }
+    assert_eq!((server.accepted, server.read), (1, 1));
+
+    match server.poll(&queue) {
+        Ok(()) => panic!("waited without events"),
+        Err(err) => assert_eq!(err.errno(), EAGAIN),
+    }
+}
+
+#[test]
+fn mock_masks_flags_and_drops_unsubscribed() {
+    let queue = MockQueue::<usize>::new();
+    let Ok(()) = queue.subscribe(7, 1, EventFlags::READ) else {
+        panic!("subscribe failed");
+    };
+    assert!(!queue.post(7, EventFlags::WRITE));
+    assert!(queue.post(7, EventFlags::READ | EventFlags::WRITE));
+    let Ok(()) = queue.unsubscribe(7) else {
+        panic!("unsubscribe failed");
+    };
+    assert_eq!(queue.pending(), 0);
+    assert_eq!(queue.subscription(7), None);
+
+    match queue.subscribe(7, usize::MAX, EventFlags::READ) {
+        Ok(()) => panic!("reserved user data was accepted"),
+        Err(err) => assert_eq!(err.errno(), EINVAL),
+    }
+}
+
+#[test]
+fn mock_works_with_combinators() {
+    let queue = MockQueue::<usize>::new().filter_events(|event| event.user_data != 1);
+    let Ok(()) = queue.subscribe(1, 1, EventFlags::READ) else {
+        panic!("subscribe failed");
+    };
+    let Ok(()) = queue.subscribe(2, 2, EventFlags::READ) else {
+        panic!("subscribe failed");
+    };
+    queue.get_ref().post(1, EventFlags::READ);
+    queue.get_ref().post(2, EventFlags::READ);
+    let Ok(event) = queue.wait() else {
+        panic!("wait failed");
+    };
+    assert_eq!((event.fd, event.user_data), (2, 2));
+}
+
+#[test]
+fn mock_backend_is_chosen_at_runtime() {
+    let Ok(queue) = DynQueue::<Token>::new(Backend::Mock) else {
+        panic!("the mock backend is always available");
+    };
+    assert_eq!(queue.backend(), Backend::Mock);
+    let mut server = Server {
+        accepted: 0,
+        read: 0,
+    };
+    let Ok(()) = server.register(&queue) else {
+        panic!("register failed");
+    };
+
+    let Some(mock) = queue.as_mock() else {
+        panic!("the mock backend has no mock queue");
+    };
+    assert!(mock.post(3, EventFlags::READ));
+    let Ok(()) = server.poll(&queue) else {
+        panic!("poll failed");
+    };
+    assert_eq!(server.accepted, 1);
+}
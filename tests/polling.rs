@@ -0,0 +1,23 @@
+//! The `polling`-style adapter.
+#![cfg(all(target_os = "redox", feature = "polling"))]
+
+use std::io::pipe;
+use std::os::fd::AsRawFd;
+
+use event::libredox::errno::EEXIST;
+use event::polling::{Event, Poller};
+
+#[test]
+fn add_refuses_a_watched_fd() {
+    let poller = Poller::new().unwrap();
+    let (reader, _writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    poller.add(fd, Event::readable(1)).unwrap();
+
+    match poller.add(fd, Event::readable(2)) {
+        Ok(()) => panic!("added a watched fd"),
+        Err(err) => assert_eq!(err.errno(), EEXIST),
+    }
+    poller.delete(fd).unwrap();
+    assert!(poller.add(fd, Event::readable(2)).is_ok());
+}
@@ -0,0 +1,64 @@
+//! Deferral of excess events by `RateLimited`.
+#![cfg(all(feature = "redox_syscall", feature = "std"))]
+
+use event::{Event, EventFlags, RateLimited, StateMachine};
+use syscall::data::Event as SyscallEvent;
+use syscall::flag::EventFlags as SyscallFlags;
+
+fn event(fd: usize, data: usize, flags: SyscallFlags) -> Event<usize> {
+    let Ok(event) = Event::try_from(SyscallEvent {
+        id: fd,
+        flags,
+        data,
+    }) else {
+        panic!("conversion failed");
+    };
+    event
+}
+
+#[test]
+fn excess_events_are_kept_per_fd() {
+    let mut delivered = Vec::new();
+    let mut limited = RateLimited::new(
+        |event: Event<usize>| delivered.push((event.fd, event.user_data, event.flags)),
+        1,
+    );
+    limited.on_event(event(1, 10, SyscallFlags::EVENT_READ));
+    limited.on_event(event(2, 20, SyscallFlags::EVENT_READ));
+    limited.on_event(event(3, 30, SyscallFlags::EVENT_READ));
+    limited.on_event(event(2, 21, SyscallFlags::EVENT_WRITE));
+    assert!(limited.is_pending());
+    assert_eq!(limited.flush(), 0);
+    drop(limited);
+
+    assert_eq!(delivered, [(1, 10, EventFlags::READ)]);
+}
+
+#[test]
+fn flush_delivers_oldest_first() {
+    let mut delivered = Vec::new();
+    let mut limited = RateLimited::new(
+        |event: Event<usize>| delivered.push((event.fd, event.user_data, event.flags)),
+        1,
+    );
+    limited.on_event(event(1, 10, SyscallFlags::EVENT_READ));
+    limited.on_event(event(2, 20, SyscallFlags::EVENT_READ));
+    limited.on_event(event(3, 30, SyscallFlags::EVENT_READ));
+    limited.on_event(event(2, 21, SyscallFlags::EVENT_WRITE));
+
+    let Some(wait) = limited.retry_after() else {
+        panic!("nothing pending");
+    };
+    std::thread::sleep(wait);
+    assert_eq!(limited.flush(), 1);
+    assert!(limited.is_pending());
+    drop(limited);
+
+    assert_eq!(
+        delivered,
+        [
+            (1, 10, EventFlags::READ),
+            (2, 21, EventFlags::READ | EventFlags::WRITE),
+        ]
+    );
+}
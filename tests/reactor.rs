@@ -0,0 +1,65 @@
+//! Futures and callbacks driven by a reactor thread.
+#![cfg(all(target_os = "redox", feature = "wrappers", feature = "std"))]
+
+use std::future::Future;
+use std::io::{pipe, Write};
+use std::os::fd::AsRawFd;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::Duration;
+
+use event::{EventFlags, Reactor};
+
+struct Unpark(thread::Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+#[test]
+fn ready_resolves_on_readiness() {
+    let (reader, mut writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    let write = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        writer.write_all(&[0]).unwrap();
+        writer
+    });
+
+    let flags = block_on(event::ready(fd, EventFlags::READ)).unwrap();
+    assert!(flags.contains(EventFlags::READ));
+    write.join().unwrap();
+}
+
+#[test]
+fn weak_handle_in_callback_lets_the_reactor_stop() {
+    let reactor = Reactor::spawn().unwrap();
+    let (reader, _writer) = pipe().unwrap();
+    let weak = reactor.downgrade();
+    let captured = weak.clone();
+    reactor
+        .register(reader.as_raw_fd() as usize, EventFlags::READ, move |_| {
+            let _ = captured.upgrade();
+        })
+        .unwrap();
+    assert!(weak.upgrade().is_some());
+
+    drop(reactor);
+    assert!(weak.upgrade().is_none());
+}
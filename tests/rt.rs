@@ -0,0 +1,106 @@
+//! Allocation-free dispatch under the `debug-rt` allocator check.
+#![cfg(all(target_os = "redox", feature = "debug-rt"))]
+
+use std::io::{pipe, Write};
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+
+use event::rt::{assert_no_alloc, RtCheckAlloc};
+use event::{EventFlags, EventLoop, EventQueue, PendingCtl, Priority};
+
+#[global_allocator]
+static ALLOC: RtCheckAlloc<std::alloc::System> = RtCheckAlloc(std::alloc::System);
+
+#[test]
+fn queue_dispatch_does_not_allocate() {
+    let queue = EventQueue::<usize>::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    let (split, mut split_writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    let split_fd = split.as_raw_fd() as usize;
+    queue.subscribe(fd, 1, EventFlags::READ).unwrap();
+    queue
+        .subscribe_interest(split_fd, 2, EventFlags::READ)
+        .unwrap();
+    queue
+        .subscribe_interest(split_fd, 3, EventFlags::READ)
+        .unwrap();
+
+    for _ in 0..2 {
+        writer.write_all(&[0]).unwrap();
+        split_writer.write_all(&[0]).unwrap();
+        queue.barrier().unwrap();
+
+        let mut seen = [0; 4];
+        let count = assert_no_alloc(|| queue.dispatch(|event| seen[event.user_data] += 1).unwrap());
+        assert_eq!(count, 3);
+        assert_eq!(seen, [0, 1, 1, 1]);
+
+        let mut buf = [0; 1];
+        std::io::Read::read_exact(&mut &reader, &mut buf).unwrap();
+        std::io::Read::read_exact(&mut &split, &mut buf).unwrap();
+    }
+}
+
+#[test]
+fn loop_dispatch_does_not_allocate_once_warm() {
+    let mut event_loop = EventLoop::<usize>::new().unwrap();
+    let (control, mut control_writer) = pipe().unwrap();
+    let (data, mut data_writer) = pipe().unwrap();
+    let control_fd = control.as_raw_fd() as usize;
+    let data_fd = data.as_raw_fd() as usize;
+    event_loop
+        .queue()
+        .subscribe(data_fd, 1, EventFlags::READ)
+        .unwrap();
+    event_loop
+        .queue()
+        .subscribe(control_fd, 2, EventFlags::READ)
+        .unwrap();
+    event_loop.set_priority(control_fd, Priority::Control);
+
+    for round in 0..2 {
+        data_writer.write_all(&[0]).unwrap();
+        control_writer.write_all(&[0]).unwrap();
+        event_loop.queue().barrier().unwrap();
+
+        let mut order = [0; 2];
+        let mut seen = 0;
+        let mut run = || {
+            event_loop
+                .run_once(|_, event| {
+                    order[seen] = event.user_data;
+                    seen += 1;
+                })
+                .unwrap()
+        };
+        // The first round sizes the buffer the loop sorts events with.
+        let count = if round == 0 {
+            run()
+        } else {
+            assert_no_alloc(run)
+        };
+        assert_eq!(count, 2);
+        assert_eq!(order, [2, 1]);
+
+        let mut buf = [0; 1];
+        std::io::Read::read_exact(&mut &control, &mut buf).unwrap();
+        std::io::Read::read_exact(&mut &data, &mut buf).unwrap();
+    }
+}
+
+#[test]
+fn pending_ctl_is_applied_outside_the_hot_path() {
+    let mut queue = EventQueue::<usize>::new().unwrap();
+    let ctl = Arc::new(PendingCtl::new());
+    queue.set_pending_ctl(Arc::clone(&ctl));
+    let (reader, mut writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+
+    ctl.subscribe(fd, 1, EventFlags::READ);
+    writer.write_all(&[0]).unwrap();
+    // Freeing the request and adding the subscription allocate, which must not abort.
+    let event = queue.next_event().unwrap();
+    assert_eq!((event.fd, event.user_data), (fd, 1));
+    assert!(ctl.is_empty());
+}
@@ -0,0 +1,42 @@
+//! Delivery semantics of a queue waited on from several places at once.
+#![cfg(all(target_os = "redox", feature = "wrappers"))]
+
+use std::io::{pipe, Write};
+use std::os::fd::AsRawFd;
+use std::thread;
+use std::time::Duration;
+
+use event::{EventFlags, RawEventQueue};
+
+#[test]
+fn each_event_is_delivered_once() {
+    let queue = RawEventQueue::new().unwrap();
+    let (reader, mut writer) = pipe().unwrap();
+    queue
+        .subscribe(reader.as_raw_fd() as usize, 7, EventFlags::READ)
+        .unwrap();
+
+    // Adopt the same fd, as a process receiving it would.
+    let fd = queue.as_raw_fd();
+    let received = thread::scope(|s| {
+        let waiters = [
+            s.spawn(|| queue.next_event_timeout(Duration::from_millis(500))),
+            s.spawn(move || {
+                let shared = unsafe { RawEventQueue::from_raw_fd(fd) };
+                let event = shared.next_event_timeout(Duration::from_millis(500));
+                let _ = shared.into_raw_fd();
+                event
+            }),
+        ];
+        writer.write_all(&[0]).unwrap();
+
+        waiters
+            .map(|waiter| waiter.join().unwrap().unwrap())
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+    });
+
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].user_data, 7);
+}
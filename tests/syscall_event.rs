@@ -0,0 +1,37 @@
+//! Conversions between `Event` and the legacy `syscall::data::Event`.
+#![cfg(all(feature = "redox_syscall", feature = "wrappers"))]
+
+use event::libredox::errno::EINVAL;
+use event::Event;
+use syscall::data::Event as SyscallEvent;
+use syscall::flag::EventFlags as SyscallFlags;
+
+#[test]
+fn round_trip() {
+    let legacy = SyscallEvent {
+        id: 7,
+        flags: SyscallFlags::EVENT_READ | SyscallFlags::EVENT_WRITE,
+        data: 42,
+    };
+    // Errors are not unwrapped, as their Debug calls into the system to describe them.
+    let Ok(event) = Event::<usize>::try_from(legacy) else {
+        panic!("conversion failed");
+    };
+    assert_eq!((event.fd, event.user_data), (7, 42));
+
+    let back = SyscallEvent::from(event);
+    assert_eq!((back.id, back.flags, back.data), (7, legacy.flags, 42));
+}
+
+#[test]
+fn reserved_user_data_is_rejected() {
+    let legacy = SyscallEvent {
+        id: 7,
+        flags: SyscallFlags::EVENT_READ,
+        data: usize::MAX,
+    };
+    match Event::<usize>::try_from(legacy) {
+        Ok(_) => panic!("reserved user data was converted"),
+        Err(err) => assert_eq!(err.errno(), EINVAL),
+    }
+}
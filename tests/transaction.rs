@@ -0,0 +1,35 @@
+//! Rolling back failed transactions.
+#![cfg(all(target_os = "redox", feature = "wrappers", feature = "alloc"))]
+
+use std::io::{self, pipe, Read, Write};
+use std::os::fd::AsRawFd;
+
+use event::{EventFlags, EventQueue};
+
+// An fd that is never open.
+const BAD_FD: usize = usize::MAX - 1;
+
+#[test]
+fn rollback_restores_nonblocking_and_fire_count() {
+    let mut queue = EventQueue::<usize>::new().unwrap();
+    queue.enable_fire_counts();
+    let (mut reader, mut writer) = pipe().unwrap();
+    let fd = reader.as_raw_fd() as usize;
+    queue
+        .subscribe_nonblocking(fd, 1, EventFlags::READ)
+        .unwrap();
+    writer.write_all(&[0]).unwrap();
+    assert_eq!(queue.next_event().unwrap().user_data, 1);
+    reader.read_exact(&mut [0]).unwrap();
+
+    let mut transaction = queue.transaction();
+    transaction
+        .unsubscribe(fd)
+        .subscribe(BAD_FD, 2, EventFlags::READ);
+    assert!(transaction.commit().is_err());
+
+    assert_eq!(queue.subscription(fd).unwrap(), Some((1, EventFlags::READ)));
+    assert_eq!(queue.fire_count(fd), Some(1));
+    let err = reader.read(&mut [0]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+}
@@ -0,0 +1,81 @@
+//! Conversions of enums declared with `user_data!`.
+#![cfg(feature = "wrappers")]
+
+use event::UserData;
+
+event::user_data! {
+    enum Implicit {
+        First,
+        Second,
+    }
+}
+
+event::user_data! {
+    enum Explicit {
+        Client = 100,
+        Timer = 200,
+        Control = 7,
+    }
+}
+
+#[test]
+fn implicit_values() {
+    assert_eq!(Implicit::Second.into_user_data(), 1);
+    assert_eq!(Implicit::try_from_user_data(0), Some(Implicit::First));
+    assert_eq!(Implicit::try_from_user_data(2), None);
+}
+
+#[test]
+fn explicit_values() {
+    for variant in [Explicit::Client, Explicit::Timer, Explicit::Control] {
+        let raw = variant.into_user_data();
+        assert_eq!(Explicit::try_from_user_data(raw), Some(variant));
+        assert_eq!(Explicit::from_user_data(raw), variant);
+    }
+    assert_eq!(Explicit::Timer.into_user_data(), 200);
+    assert_eq!(Explicit::try_from_user_data(0), None);
+    assert_eq!(Explicit::try_from_user_data(101), None);
+}
+
+#[test]
+#[should_panic]
+fn explicit_invalid_panics() {
+    Explicit::from_user_data(150);
+}
+
+#[test]
+fn reserved_values_are_rejected() {
+    assert_eq!(
+        usize::try_from_user_data(event::MAX_APP_VALUE),
+        Some(event::MAX_APP_VALUE)
+    );
+    assert_eq!(usize::try_from_user_data(event::MAX_APP_VALUE + 1), None);
+}
+
+event::user_data! {
+    enum Ranged {
+        Listener = 1,
+        Client(100..200),
+        Timer(10..20),
+    }
+}
+
+#[test]
+fn range_values() {
+    assert_eq!(Ranged::Listener.into_user_data(), 1);
+    assert_eq!(Ranged::Client(0).into_user_data(), 100);
+    assert_eq!(Ranged::Client(99).into_user_data(), 199);
+    assert_eq!(Ranged::Timer(5).into_user_data(), 15);
+
+    assert_eq!(Ranged::try_from_user_data(1), Some(Ranged::Listener));
+    assert_eq!(Ranged::try_from_user_data(142), Some(Ranged::Client(42)));
+    assert_eq!(Ranged::try_from_user_data(10), Some(Ranged::Timer(0)));
+    assert_eq!(Ranged::try_from_user_data(200), None);
+    assert_eq!(Ranged::try_from_user_data(0), None);
+}
+
+#[test]
+#[should_panic]
+fn range_index_out_of_range_panics() {
+    Ranged::Client(100).into_user_data();
+}
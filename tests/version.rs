@@ -0,0 +1,24 @@
+//! Checking the event ABI version up front.
+#![cfg(all(target_os = "redox", feature = "wrappers"))]
+
+use event::libredox::errno::ENOSYS;
+use event::{abi_version, EventQueue};
+
+#[test]
+fn older_versions_are_accepted() {
+    let (major, minor) = abi_version();
+    assert!(EventQueue::<usize>::with_min_version((major, minor)).is_ok());
+    assert!(EventQueue::<usize>::with_min_version((major, 0)).is_ok());
+    assert!(EventQueue::<usize>::with_min_version((major - 1, minor + 1)).is_ok());
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let (major, minor) = abi_version();
+    for version in [(major, minor + 1), (major + 1, 0)] {
+        match EventQueue::<usize>::with_min_version(version) {
+            Ok(_) => panic!("version {version:?} was accepted"),
+            Err(err) => assert_eq!(err.errno(), ENOSYS),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Coalescing of `EventWaker` wakes, which only needs a pipe and runs on any host.
+#![cfg(feature = "std")]
+
+use std::thread;
+
+use event::EventWaker;
+
+#[test]
+fn wakes_are_counted_once() {
+    let waker = EventWaker::new().unwrap();
+    assert_eq!(waker.wake_count().unwrap(), 0);
+
+    for _ in 0..1_000_000 {
+        waker.wake().unwrap();
+    }
+    assert_eq!(waker.wake_count().unwrap(), 1_000_000);
+    assert_eq!(waker.wake_count().unwrap(), 0);
+}
+
+#[test]
+fn concurrent_wakes_never_block() {
+    const THREADS: u64 = 8;
+    const WAKES: u64 = 250_000;
+
+    let waker = EventWaker::new().unwrap();
+    let total = thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..WAKES {
+                    waker.wake().unwrap();
+                }
+            });
+        }
+        let mut total = 0;
+        while total < THREADS * WAKES {
+            total += waker.wake_count().unwrap();
+        }
+        total
+    });
+    assert_eq!(total, THREADS * WAKES);
+    assert_eq!(waker.wake_count().unwrap(), 0);
+}
+
+#[test]
+fn wake_count_without_wake_does_not_block() {
+    let waker = EventWaker::new().unwrap();
+    for _ in 0..3 {
+        assert_eq!(waker.wake_count().unwrap(), 0);
+    }
+    waker.wake().unwrap();
+    assert_eq!(waker.wake_count().unwrap(), 1);
+    assert_eq!(waker.wake_count().unwrap(), 0);
+}